@@ -0,0 +1,183 @@
+use bevy::prelude::*;
+use crate::PlayingState;
+use crate::effect::{Explode, ExplosionKind};
+use crate::enemy::{Bonus, Enemy};
+use crate::food::FoodDrop;
+use crate::load::{GameConfig, GameMap, GameTexture, LandType};
+use crate::score::EnemyKilled;
+use crate::stage::StageEntity;
+use crate::world::{GAME_BLOCK, GAME_BULLET_SIZE, GAME_DIRECTION_SPEED, GAME_TANK_SIZE, FireKeys, GameDirection, GameMode, Moving, Player1, Player2, PlayerHit, AnyPlayer, Appear, Home, Shield, TankTier};
+
+pub struct BulletPlugin;
+
+impl Plugin for BulletPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, (player1_fire, player2_fire.run_if(resource_equals(GameMode::Double)), (bullet_moving, bullet_hit_land, apply_deferred, bullet_hit_enemy, bullet_hit_player, apply_deferred, bullet_hit_home).chain()).run_if(in_state(PlayingState::Running)));
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Camp {
+    Player,
+    Enemy,
+}
+
+#[derive(Component)]
+pub struct Bullet {
+    pub owner: Entity,
+    pub camp: Camp,
+    pub break_iron: bool,
+}
+
+//从坦克炮口位置生成一颗子弹
+pub fn spawn_bullet(commands: &mut Commands, game_texture: &GameTexture, bullet: Bullet, position: Vec3, direction: &GameDirection, speed: f32) {
+    commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(0),
+        texture_atlas: game_texture.bullet(direction),
+        transform: Transform::from_translation(position + GAME_DIRECTION_SPEED[direction] * GAME_BLOCK.x),
+        ..Default::default()
+    }).insert(bullet).insert(Moving {
+        speed,
+        direction: direction.clone(),
+        slide: 0.,
+    }).insert(StageEntity);
+}
+
+//每按一次开一炮, 同时在飞的子弹数量由坦克等级决定
+fn player1_fire(mut commands: Commands, keyboard_input: Res<Input<KeyCode>>, fire_keys: Res<FireKeys>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, query: Query<(Entity, &Transform, &Moving, &TankTier), (With<Player1>, Without<Appear>)>, bullets: Query<&Bullet>) {
+    if !keyboard_input.just_pressed(fire_keys.player1) {
+        return;
+    }
+    for (entity, transform, moving, tier) in &query {
+        let tier_config = game_config.tier(tier.0);
+        if bullets.iter().filter(|bullet| bullet.owner == entity).count() < tier_config.bullets {
+            spawn_bullet(&mut commands, &game_texture, Bullet { owner: entity, camp: Camp::Player, break_iron: tier_config.break_iron }, transform.translation, &moving.direction, tier_config.bullet_speed);
+        }
+    }
+}
+
+//每按一次开一炮, 同时在飞的子弹数量由坦克等级决定
+fn player2_fire(mut commands: Commands, keyboard_input: Res<Input<KeyCode>>, fire_keys: Res<FireKeys>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, query: Query<(Entity, &Transform, &Moving, &TankTier), (With<Player2>, Without<Appear>)>, bullets: Query<&Bullet>) {
+    if !keyboard_input.just_pressed(fire_keys.player2) {
+        return;
+    }
+    for (entity, transform, moving, tier) in &query {
+        let tier_config = game_config.tier(tier.0);
+        if bullets.iter().filter(|bullet| bullet.owner == entity).count() < tier_config.bullets {
+            spawn_bullet(&mut commands, &game_texture, Bullet { owner: entity, camp: Camp::Player, break_iron: tier_config.break_iron }, transform.translation, &moving.direction, tier_config.bullet_speed);
+        }
+    }
+}
+
+fn bullet_moving(mut query: Query<(&mut Transform, &Moving), With<Bullet>>) {
+    for (mut transform, moving) in &mut query {
+        transform.translation += moving.speed * GAME_DIRECTION_SPEED[&moving.direction];
+    }
+}
+
+//子弹打中砖块时去掉迎面一排的四分之一格, 铁块只有最高级坦克才能打掉, 碰到地图边缘的子弹直接销毁
+//world.boundary 是坦克中心能到的范围, 比地图小半辆坦克, 用它判断的话最外面半格砖永远打不到
+fn bullet_hit_land(mut commands: Commands, mut explode: EventWriter<Explode>, game_config: Res<GameConfig>, mut game_map: ResMut<GameMap>, query: Query<(Entity, &Transform, &Moving, &Bullet)>) {
+    let step = game_config.world.step;
+    let edge = Rect::from_center_size(Vec2::ZERO, game_map.size * step - *GAME_BULLET_SIZE);
+    for (entity, transform, moving, bullet) in &query {
+        let pos = transform.translation.truncate();
+        let hit = game_map.parts(Rect::from_center_size(pos, *GAME_BULLET_SIZE), step).into_iter()
+            .filter(|(_, land)| land.stops_bullet())
+            .map(|(part, _)| part.rect)
+            .reduce(|nearest, rect| match moving.direction {
+                GameDirection::Up => if rect.min.y < nearest.min.y { rect } else { nearest },
+                GameDirection::Down => if rect.max.y > nearest.max.y { rect } else { nearest },
+                GameDirection::Left => if rect.max.x > nearest.max.x { rect } else { nearest },
+                GameDirection::Right => if rect.min.x < nearest.min.x { rect } else { nearest },
+            });
+        let Some(hit) = hit else {
+            if !edge.contains(transform.translation.truncate()) {
+                commands.entity(entity).despawn();
+                explode.send(Explode { position: transform.translation, kind: ExplosionKind::Small });
+            }
+            continue;
+        };
+        let (half_width, depth) = (GAME_TANK_SIZE.x / 2., step / 2.);
+        let blast = match moving.direction {
+            GameDirection::Up => Rect::new(pos.x - half_width, hit.min.y, pos.x + half_width, hit.min.y + depth),
+            GameDirection::Down => Rect::new(pos.x - half_width, hit.max.y - depth, pos.x + half_width, hit.max.y),
+            GameDirection::Left => Rect::new(hit.max.x - depth, pos.y - half_width, hit.max.x, pos.y + half_width),
+            GameDirection::Right => Rect::new(hit.min.x, pos.y - half_width, hit.min.x + depth, pos.y + half_width),
+        };
+        let broken: Vec<_> = game_map.parts(blast, step).into_iter()
+            .filter(|(_, land)| matches!(land, LandType::Brick(_)) || (bullet.break_iron && matches!(land, LandType::Iron)))
+            .map(|(part, _)| part)
+            .collect();
+        for part in broken.iter() {
+            game_map.remove_part(part);
+        }
+        commands.entity(entity).despawn();
+        explode.send(Explode { position: transform.translation, kind: ExplosionKind::Small });
+    }
+}
+
+//玩家子弹打中敌人时扣血, 血量为零时销毁敌人并给开炮的玩家记分, 携带道具的敌人第一次被打中时掉落道具
+pub fn bullet_hit_enemy(mut commands: Commands, mut food_drop: EventWriter<FoodDrop>, mut explode: EventWriter<Explode>, mut enemy_killed: EventWriter<EnemyKilled>, bullets: Query<(Entity, &Transform, &Bullet)>, mut enemies: Query<(Entity, &Transform, &mut Enemy, Has<Bonus>), Without<Appear>>) {
+    for (bullet_entity, bullet_transform, bullet) in &bullets {
+        if bullet.camp != Camp::Player {
+            continue;
+        }
+        let rect = Rect::from_center_size(bullet_transform.translation.truncate(), *GAME_BULLET_SIZE);
+        let hit = enemies.iter_mut().find(|(_, transform, enemy, _)| {
+            enemy.health > 0 && !rect.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_TANK_SIZE)).is_empty()
+        });
+        if let Some((enemy_entity, enemy_transform, mut enemy, bonus)) = hit {
+            commands.entity(bullet_entity).despawn();
+            if bonus {
+                commands.entity(enemy_entity).remove::<Bonus>();
+                food_drop.send(FoodDrop);
+            }
+            enemy.health -= 1;
+            if enemy.health == 0 {
+                commands.entity(enemy_entity).despawn_recursive();
+                explode.send(Explode { position: enemy_transform.translation, kind: ExplosionKind::Big });
+                enemy_killed.send(EnemyKilled { by: bullet.owner, kind: enemy.kind });
+            } else {
+                explode.send(Explode { position: bullet_transform.translation, kind: ExplosionKind::Small });
+            }
+        }
+    }
+}
+
+//敌人子弹打中玩家, 同一帧内每个玩家只算一次
+fn bullet_hit_player(mut commands: Commands, mut player_hit: EventWriter<PlayerHit>, mut explode: EventWriter<Explode>, bullets: Query<(Entity, &Transform, &Bullet)>, players: Query<(Entity, &Transform, Has<Shield>), (AnyPlayer, Without<Appear>)>) {
+    let mut hit_players = Vec::new();
+    for (bullet_entity, bullet_transform, bullet) in &bullets {
+        if bullet.camp != Camp::Enemy {
+            continue;
+        }
+        let rect = Rect::from_center_size(bullet_transform.translation.truncate(), *GAME_BULLET_SIZE);
+        let hit = players.iter().find(|(_, transform, _)| !rect.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_TANK_SIZE)).is_empty());
+        if let Some((player_entity, player_transform, shielded)) = hit {
+            commands.entity(bullet_entity).despawn();
+            if !hit_players.contains(&player_entity) {
+                hit_players.push(player_entity);
+                player_hit.send(PlayerHit(player_entity));
+                let kind = if shielded { ExplosionKind::Small } else { ExplosionKind::Big };
+                explode.send(Explode { position: player_transform.translation, kind });
+            }
+        }
+    }
+}
+
+//任何一方的子弹打中基地都会结束游戏
+fn bullet_hit_home(mut commands: Commands, mut explode: EventWriter<Explode>, game_texture: Res<GameTexture>, mut playing_state: ResMut<NextState<PlayingState>>, bullets: Query<(Entity, &Transform), With<Bullet>>, mut home: Query<(&Transform, &mut Handle<TextureAtlas>), With<Home>>) {
+    let Ok((home_transform, mut texture_atlas)) = home.get_single_mut() else {
+        return;
+    };
+    let home_rect = Rect::from_center_size(home_transform.translation.truncate(), *GAME_TANK_SIZE);
+    for (entity, transform) in &bullets {
+        if !home_rect.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_BULLET_SIZE)).is_empty() {
+            commands.entity(entity).despawn();
+            *texture_atlas = game_texture.home_destroyed.clone();
+            playing_state.set(PlayingState::GameOver);
+            explode.send(Explode { position: home_transform.translation, kind: ExplosionKind::Big });
+        }
+    }
+}
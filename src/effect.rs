@@ -0,0 +1,67 @@
+use bevy::prelude::*;
+use crate::{GameState, PlayingState};
+use crate::load::GameTexture;
+use crate::stage::StageEntity;
+
+pub struct EffectPlugin;
+
+impl Plugin for EffectPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<Explode>()
+            .add_systems(Update, explode.run_if(in_state(GameState::Playing)))
+            .add_systems(Update, explosion_update.run_if(in_state(PlayingState::Running).or_else(in_state(PlayingState::GameOver))));
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ExplosionKind {
+    //子弹命中
+    Small,
+    //坦克或基地被摧毁
+    Big,
+}
+
+#[derive(Event)]
+pub struct Explode {
+    pub position: Vec3,
+    pub kind: ExplosionKind,
+}
+
+//按帧播放, 播完自动销毁
+#[derive(Component)]
+pub struct Explosion {
+    frame: Timer,
+    frames: usize,
+}
+
+//爆炸显示在最上层, 基地被打爆时游戏已经结束, 所以生成不受暂停和结束影响
+fn explode(mut commands: Commands, mut events: EventReader<Explode>, game_texture: Res<GameTexture>) {
+    for event in events.read() {
+        let (texture_atlas, frames, seconds) = match event.kind {
+            ExplosionKind::Small => (game_texture.boom_static.clone(), 1, 0.1),
+            ExplosionKind::Big => (game_texture.boom_dynamic.clone(), 6, 0.06),
+        };
+        commands.spawn(SpriteSheetBundle {
+            sprite: TextureAtlasSprite::new(0),
+            texture_atlas,
+            transform: Transform::from_translation(event.position.truncate().extend(3.)),
+            ..Default::default()
+        }).insert(Explosion {
+            frame: Timer::from_seconds(seconds, TimerMode::Repeating),
+            frames,
+        }).insert(StageEntity);
+    }
+}
+
+//游戏结束后画面停住, 基地的爆炸还要播完
+fn explosion_update(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Explosion, &mut TextureAtlasSprite)>) {
+    for (entity, mut explosion, mut sprite) in &mut query {
+        if explosion.frame.tick(time.delta()).just_finished() {
+            if sprite.index + 1 >= explosion.frames {
+                commands.entity(entity).despawn();
+            } else {
+                sprite.index += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::PlayingState;
+use crate::bullet::{Bullet, Camp, spawn_bullet};
+use crate::food::EnemyFreeze;
+use crate::level::GameLevel;
+use crate::load::{GameConfig, GameMap, GameTexture};
+use crate::stage::StageEntity;
+use crate::world::{GAME_TANK_DIRECTION_INDEX, GAME_TANK_SIZE, GameDirection, Moving, next_position, add_appear, Appear, AnyPlayer, to_world_position};
+
+pub struct EnemyPlugin;
+
+impl Plugin for EnemyPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(OnEnter(PlayingState::Intro), enemy_spawner_setup)
+            .add_systems(Update, ((enemy_spawn, apply_deferred, enemy_cleared).chain(), enemy_brain, enemy_fire, enemy_turns, enemy_texture, bonus_blink).run_if(in_state(PlayingState::Running)));
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum EnemyKind {
+    Basic,
+    Fast,
+    Power,
+    Armor,
+}
+
+#[derive(Component)]
+pub struct Enemy {
+    pub kind: EnemyKind,
+    pub health: u32,
+}
+
+impl Enemy {
+    //装甲坦克按剩余血量换颜色
+    pub fn variant(&self) -> usize {
+        (self.health as usize).saturating_sub(1).min(2)
+    }
+}
+
+#[derive(Component)]
+pub struct EnemyBrain {
+    turn_timer: Timer,
+    fire_timer: Timer,
+}
+
+impl EnemyBrain {
+    fn new(fire_interval: f32) -> Self {
+        EnemyBrain {
+            turn_timer: random_turn_timer(),
+            fire_timer: Timer::from_seconds(fire_interval, TimerMode::Repeating),
+        }
+    }
+}
+
+//携带道具的敌人, 红色闪烁
+#[derive(Component)]
+pub struct Bonus {
+    timer: Timer,
+    red: bool,
+}
+
+#[derive(Resource)]
+pub struct EnemySpawner {
+    pub roster: VecDeque<EnemyKind>,
+    next_point: usize,
+    timer: Timer,
+}
+
+fn enemy_spawner_setup(mut commands: Commands, game_config: Res<GameConfig>, game_level: Res<GameLevel>) {
+    commands.insert_resource(EnemySpawner {
+        roster: game_level.roster.iter().copied().collect(),
+        next_point: 0,
+        timer: Timer::from_seconds(game_config.enemy.spawn_interval, TimerMode::Repeating),
+    });
+}
+
+//按出场顺序轮流在出生点生成敌人, 场上敌人有数量上限, 出生点被坦克占着时等下一轮
+fn enemy_spawn(mut commands: Commands, time: Res<Time>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_level: Res<GameLevel>, mut spawner: ResMut<EnemySpawner>, tanks: Query<(&Transform, Has<Enemy>), (With<Moving>, Without<Bullet>)>) {
+    let spawn_points = &game_level.spawn_points;
+    if !spawner.timer.tick(time.delta()).finished() || tanks.iter().filter(|(_, is_enemy)| *is_enemy).count() >= game_config.enemy.max_alive || spawn_points.is_empty() {
+        return;
+    }
+    let Some(&kind) = spawner.roster.front() else {
+        return;
+    };
+    let position = to_world_position(&spawn_points[spawner.next_point % spawn_points.len()]);
+    spawner.next_point += 1;
+    let area = Rect::from_center_size(position.truncate(), *GAME_TANK_SIZE);
+    if tanks.iter().any(|(transform, _)| !area.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_TANK_SIZE)).is_empty()) {
+        return;
+    }
+    let index = game_level.roster.len() - spawner.roster.len();
+    spawner.roster.pop_front();
+    let kind_config = &game_config.enemy.kinds[&kind];
+    let enemy = Enemy { kind, health: kind_config.health };
+    let mut entity = commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(GAME_TANK_DIRECTION_INDEX[&GameDirection::Down][0]),
+        texture_atlas: game_texture.enemy(&kind, enemy.variant()),
+        transform: Transform::from_translation(position),
+        ..Default::default()
+    });
+    entity.insert(enemy).insert(EnemyBrain::new(kind_config.fire_interval)).insert(Moving {
+        speed: kind_config.speed,
+        direction: GameDirection::Down,
+        slide: 0.,
+    }).insert(StageEntity);
+    if game_config.enemy.bonus.contains(&index) {
+        entity.insert(Bonus {
+            timer: Timer::from_seconds(game_config.food.blink, TimerMode::Repeating),
+            red: false,
+        });
+    }
+    let entity = entity.id();
+    add_appear(&mut commands, &game_texture, entity, game_config.spawn.appear);
+}
+
+//敌人全部出场并且都被消灭后过关
+fn enemy_cleared(spawner: Res<EnemySpawner>, enemies: Query<(), With<Enemy>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if spawner.roster.is_empty() && enemies.is_empty() {
+        playing_state.set(PlayingState::Cleared);
+    }
+}
+
+fn random_turn_timer() -> Timer {
+    Timer::from_seconds(1. + fastrand::f32() * 2., TimerMode::Once)
+}
+
+//朝目标的方向, 距离远的轴优先
+fn toward(from: Vec3, to: Vec3) -> [GameDirection; 2] {
+    let offset = to - from;
+    let horizontal = if offset.x < 0. { GameDirection::Left } else { GameDirection::Right };
+    let vertical = if offset.y < 0. { GameDirection::Down } else { GameDirection::Up };
+    if offset.x.abs() > offset.y.abs() { [horizontal, vertical] } else { [vertical, horizontal] }
+}
+
+//敌人默认随机游走, 被挡住或到时间就换方向, 按类型的进攻性偏向基地或最近的玩家, 被定住时原地不动
+fn enemy_brain(time: Res<Time>, game_config: Res<GameConfig>, game_map: Res<GameMap>, freeze: Option<Res<EnemyFreeze>>, mut query: Query<(&Transform, &Enemy, &mut Moving, &mut EnemyBrain), Without<Appear>>, players: Query<&Transform, AnyPlayer>) {
+    let home = to_world_position(&game_map.home);
+    for (transform, enemy, mut moving, mut brain) in &mut query {
+        let kind_config = &game_config.enemy.kinds[&enemy.kind];
+        if freeze.is_some() {
+            moving.speed = 0.;
+            continue;
+        }
+        moving.speed = kind_config.speed;
+        let blocked = next_position(transform.translation, &moving, &game_config, &game_map).is_none();
+        if blocked || brain.turn_timer.tick(time.delta()).finished() {
+            let mut directions = Vec::new();
+            if fastrand::f32() < kind_config.aggression {
+                let nearest_player = players.iter()
+                    .map(|player| player.translation)
+                    .min_by(|a, b| a.distance_squared(transform.translation).total_cmp(&b.distance_squared(transform.translation)));
+                let target = match nearest_player {
+                    Some(player) if fastrand::bool() => player,
+                    _ => home,
+                };
+                directions.extend(toward(transform.translation, target));
+            }
+            let mut wander = [GameDirection::Up, GameDirection::Down, GameDirection::Left, GameDirection::Right];
+            fastrand::shuffle(&mut wander);
+            directions.extend(wander);
+            let free = directions.into_iter().find(|direction| {
+                let turned = Moving { direction: direction.clone(), ..moving.clone() };
+                next_position(transform.translation, &turned, &game_config, &game_map).is_some()
+            });
+            if let Some(direction) = free {
+                moving.direction = direction;
+            }
+            brain.turn_timer = random_turn_timer();
+        }
+    }
+}
+
+//敌人定时开火, 同时只有一颗子弹在飞
+fn enemy_fire(mut commands: Commands, time: Res<Time>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, freeze: Option<Res<EnemyFreeze>>, mut query: Query<(Entity, &Transform, &Enemy, &Moving, &mut EnemyBrain), Without<Appear>>, bullets: Query<&Bullet>) {
+    if freeze.is_some() {
+        return;
+    }
+    for (entity, transform, enemy, moving, mut brain) in &mut query {
+        let kind_config = &game_config.enemy.kinds[&enemy.kind];
+        if brain.fire_timer.tick(time.delta()).just_finished() && !bullets.iter().any(|bullet| bullet.owner == entity) {
+            spawn_bullet(&mut commands, &game_texture, Bullet { owner: entity, camp: Camp::Enemy, break_iron: false }, transform.translation, &moving.direction, kind_config.bullet_speed);
+        }
+    }
+}
+
+fn enemy_turns(mut query: Query<(&mut TextureAtlasSprite, &Moving), With<Enemy>>) {
+    for (mut sprite, moving) in &mut query {
+        let indices = GAME_TANK_DIRECTION_INDEX[&moving.direction];
+        if moving.speed > 0. {
+            sprite.index = if sprite.index == indices[0] { indices[1] } else { indices[0] };
+        }
+    }
+}
+
+//血量变化后按剩余血量换颜色
+fn enemy_texture(game_texture: Res<GameTexture>, mut query: Query<(&Enemy, &mut Handle<TextureAtlas>), Changed<Enemy>>) {
+    for (enemy, mut texture_atlas) in &mut query {
+        *texture_atlas = game_texture.enemy(&enemy.kind, enemy.variant());
+    }
+}
+
+fn bonus_blink(time: Res<Time>, game_texture: Res<GameTexture>, mut query: Query<(&Enemy, &mut Bonus, &mut Handle<TextureAtlas>)>) {
+    for (enemy, mut bonus, mut texture_atlas) in &mut query {
+        if bonus.timer.tick(time.delta()).just_finished() {
+            bonus.red = !bonus.red;
+            *texture_atlas = game_texture.enemy(&enemy.kind, if bonus.red { 3 } else { enemy.variant() });
+        }
+    }
+}
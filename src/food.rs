@@ -0,0 +1,317 @@
+use bevy::prelude::*;
+use crate::PlayingState;
+use crate::bullet::Bullet;
+use crate::effect::{Explode, ExplosionKind};
+use crate::enemy::Enemy;
+use crate::load::{GameConfig, GameMap, GameTexture, LandType};
+use crate::stage::StageEntity;
+use crate::world::{GAME_MAX_TANK_TIER, GAME_TANK_SIZE, AnyPlayer, Lives, Moving, Shield, TankTier, add_shield, appear_update};
+
+pub struct FoodPlugin;
+
+impl Plugin for FoodPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FoodDrop>().add_event::<FoodPicked>()
+            .add_systems(Update, (food_spawn, food_update, food_pick, freeze_update, fortify_update).run_if(in_state(PlayingState::Running)))
+            //出场闪光结束时要看到同一帧头盔加上的护盾
+            .add_systems(Update, (food_star, food_tank, food_clock, food_boom, food_iron, food_protect.before(appear_update), food_gun).run_if(in_state(PlayingState::Running)));
+    }
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum FoodKind {
+    Star,
+    Tank,
+    Clock,
+    Boom,
+    Iron,
+    Protect,
+    Gun,
+}
+
+const FOOD_KINDS: [FoodKind; 7] = [FoodKind::Star, FoodKind::Tank, FoodKind::Clock, FoodKind::Boom, FoodKind::Iron, FoodKind::Protect, FoodKind::Gun];
+
+#[derive(Component)]
+pub struct Food {
+    kind: FoodKind,
+    lifetime: Timer,
+    blink: Timer,
+}
+
+//携带道具的敌人被打中时掉落一个道具
+#[derive(Event)]
+pub struct FoodDrop;
+
+#[derive(Event)]
+pub struct FoodPicked {
+    pub player: Entity,
+    pub kind: FoodKind,
+}
+
+//存在期间敌人全部停住
+#[derive(Resource)]
+pub struct EnemyFreeze(pub Timer);
+
+//存在期间基地周围是铁块, cells 记着换成铁块的格子原来的地形, 结束时原样恢复
+#[derive(Resource)]
+pub struct Fortify {
+    timer: Timer,
+    cells: Vec<((i32, i32), LandType)>,
+}
+
+//道具出现在随机的空位上, 同一时间只有一个道具
+fn food_spawn(mut commands: Commands, mut events: EventReader<FoodDrop>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_map: Res<GameMap>, foods: Query<Entity, With<Food>>) {
+    if events.read().count() == 0 {
+        return;
+    }
+    let step = game_config.world.step;
+    let boundary = &game_config.world.boundary;
+    let (min_x, max_x) = ((boundary.min.x / step).ceil() as i32, (boundary.max.x / step).floor() as i32);
+    let (min_y, max_y) = ((boundary.min.y / step).ceil() as i32, (boundary.max.y / step).floor() as i32);
+    let position = (0..100)
+        .map(|_| Vec2::new(fastrand::i32(min_x..=max_x) as f32, fastrand::i32(min_y..=max_y) as f32) * step)
+        .find(|position| !game_map.is_blocked(Rect::from_center_size(*position, *GAME_TANK_SIZE), step));
+    let Some(position) = position else {
+        return;
+    };
+    for entity in &foods {
+        commands.entity(entity).despawn();
+    }
+    let kind = FOOD_KINDS[fastrand::usize(..FOOD_KINDS.len())];
+    commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(0),
+        texture_atlas: game_texture.food(&kind),
+        transform: Transform::from_translation(position.extend(2.)),
+        ..Default::default()
+    }).insert(Food {
+        kind,
+        lifetime: Timer::from_seconds(game_config.food.lifetime, TimerMode::Once),
+        blink: Timer::from_seconds(game_config.food.blink, TimerMode::Repeating),
+    }).insert(StageEntity);
+}
+
+//道具一直闪烁, 时间到了消失
+fn food_update(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Food, &mut Visibility)>) {
+    for (entity, mut food, mut visibility) in &mut query {
+        if food.lifetime.tick(time.delta()).finished() {
+            commands.entity(entity).despawn();
+        } else if food.blink.tick(time.delta()).just_finished() {
+            *visibility = if *visibility == Visibility::Hidden { Visibility::Inherited } else { Visibility::Hidden };
+        }
+    }
+}
+
+fn food_pick(mut commands: Commands, mut food_picked: EventWriter<FoodPicked>, foods: Query<(Entity, &Transform, &Food)>, players: Query<(Entity, &Transform), AnyPlayer>) {
+    for (food_entity, food_transform, food) in &foods {
+        let rect = Rect::from_center_size(food_transform.translation.truncate(), *GAME_TANK_SIZE);
+        let picker = players.iter().find(|(_, transform)| !rect.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_TANK_SIZE)).is_empty());
+        if let Some((player, _)) = picker {
+            commands.entity(food_entity).despawn();
+            food_picked.send(FoodPicked { player, kind: food.kind });
+        }
+    }
+}
+
+//星星: 坦克升一级
+fn food_star(mut events: EventReader<FoodPicked>, mut query: Query<&mut TankTier>) {
+    for picked in events.read().filter(|picked| picked.kind == FoodKind::Star) {
+        if let Ok(mut tier) = query.get_mut(picked.player) {
+            tier.0 = (tier.0 + 1).min(GAME_MAX_TANK_TIER);
+        }
+    }
+}
+
+//坦克: 加一条命
+fn food_tank(mut events: EventReader<FoodPicked>, mut query: Query<&mut Lives>) {
+    for picked in events.read().filter(|picked| picked.kind == FoodKind::Tank) {
+        if let Ok(mut lives) = query.get_mut(picked.player) {
+            lives.0 += 1;
+        }
+    }
+}
+
+//时钟: 定住所有敌人
+fn food_clock(mut commands: Commands, mut events: EventReader<FoodPicked>, game_config: Res<GameConfig>) {
+    if events.read().any(|picked| picked.kind == FoodKind::Clock) {
+        commands.insert_resource(EnemyFreeze(Timer::from_seconds(game_config.food.freeze, TimerMode::Once)));
+    }
+}
+
+//炸弹: 消灭场上所有敌人
+fn food_boom(mut commands: Commands, mut events: EventReader<FoodPicked>, mut explode: EventWriter<Explode>, enemies: Query<(Entity, &Transform), With<Enemy>>) {
+    if events.read().any(|picked| picked.kind == FoodKind::Boom) {
+        for (entity, transform) in &enemies {
+            commands.entity(entity).despawn_recursive();
+            explode.send(Explode { position: transform.translation, kind: ExplosionKind::Big });
+        }
+    }
+}
+
+//铁锹: 基地周围换成铁块, 有坦克压着的格子不换, 免得把坦克卡住
+//已经加固时重新计时, 被打掉的铁块也补上, cells 里只保留第一次记下的原来的地形
+fn food_iron(mut commands: Commands, mut events: EventReader<FoodPicked>, game_config: Res<GameConfig>, mut game_map: ResMut<GameMap>, fortify: Option<Res<Fortify>>, tanks: Query<&Transform, (With<Moving>, Without<Bullet>)>) {
+    if !events.read().any(|picked| picked.kind == FoodKind::Iron) {
+        return;
+    }
+    let step = game_config.world.step;
+    let mut cells = fortify.map(|fortify| fortify.cells.clone()).unwrap_or_default();
+    for (col, row) in home_ring(&game_map, step) {
+        if tank_on_cell(&game_map, col, row, step, &tanks) {
+            continue;
+        }
+        let Some(land) = game_map.get(col, row).cloned() else {
+            continue;
+        };
+        if !cells.iter().any(|(cell, _)| *cell == (col, row)) {
+            cells.push(((col, row), land));
+        }
+        game_map.set(col, row, LandType::Iron);
+    }
+    commands.insert_resource(Fortify {
+        timer: Timer::from_seconds(game_config.food.fortify, TimerMode::Once),
+        cells,
+    });
+}
+
+//头盔: 一段时间内无敌, 已有护盾时延长时间
+fn food_protect(mut commands: Commands, mut events: EventReader<FoodPicked>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, mut query: Query<Option<&mut Shield>>) {
+    for picked in events.read().filter(|picked| picked.kind == FoodKind::Protect) {
+        if let Ok(shield) = query.get_mut(picked.player) {
+            add_shield(&mut commands, &game_texture, picked.player, shield, game_config.food.shield);
+        }
+    }
+}
+
+//手枪: 坦克直接升到最高级
+fn food_gun(mut events: EventReader<FoodPicked>, mut query: Query<&mut TankTier>) {
+    for picked in events.read().filter(|picked| picked.kind == FoodKind::Gun) {
+        if let Ok(mut tier) = query.get_mut(picked.player) {
+            tier.0 = GAME_MAX_TANK_TIER;
+        }
+    }
+}
+
+fn freeze_update(mut commands: Commands, time: Res<Time>, freeze: Option<ResMut<EnemyFreeze>>) {
+    if let Some(mut freeze) = freeze {
+        if freeze.0.tick(time.delta()).finished() {
+            commands.remove_resource::<EnemyFreeze>();
+        }
+    }
+}
+
+//加固时间到了基地周围恢复成原来的地形, 铁块被打掉后开进去的坦克压着的格子保持现状
+fn fortify_update(mut commands: Commands, time: Res<Time>, game_config: Res<GameConfig>, fortify: Option<ResMut<Fortify>>, mut game_map: ResMut<GameMap>, tanks: Query<&Transform, (With<Moving>, Without<Bullet>)>) {
+    let Some(mut fortify) = fortify else {
+        return;
+    };
+    if !fortify.timer.tick(time.delta()).finished() {
+        return;
+    }
+    let step = game_config.world.step;
+    for ((col, row), land) in fortify.cells.drain(..) {
+        if !tank_on_cell(&game_map, col, row, step, &tanks) {
+            game_map.set(col, row, land);
+        }
+    }
+    commands.remove_resource::<Fortify>();
+}
+
+fn tank_on_cell(game_map: &GameMap, col: i32, row: i32, step: f32, tanks: &Query<&Transform, (With<Moving>, Without<Bullet>)>) -> bool {
+    let rect = game_map.cell_rect(col, row, step);
+    tanks.iter().any(|transform| !rect.intersect(Rect::from_center_size(transform.translation.truncate(), *GAME_TANK_SIZE)).is_empty())
+}
+
+//基地外面一圈格子
+fn home_ring(game_map: &GameMap, step: f32) -> Vec<(i32, i32)> {
+    let home = game_map.home_rect(step);
+    let inner = game_map.cells(home, step);
+    game_map.cells(home.inset(step), step).into_iter().filter(|cell| !inner.contains(cell)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use bevy::ecs::system::RunSystemOnce;
+    use super::*;
+    use crate::load::BRICK_FULL;
+    use crate::world::GameDirection;
+
+    const HOME: (i32, i32) = (2, 4);
+
+    //6x6的地图, 基地占第2,3列的第4,5行, 外面一圈在地图里的有8格
+    fn world() -> World {
+        let mut world = World::new();
+        let game_config: GameConfig = serde_json::from_str(include_str!("../assets/resource.json")).unwrap();
+        let mut game_map = GameMap::new(Vec2::new(6., 6.), Vec3::new(0., -2., 1.));
+        game_map.set(1, 3, LandType::Brick(BRICK_FULL));
+        game_map.set(1, 5, LandType::Water);
+        world.insert_resource(game_config);
+        world.insert_resource(game_map);
+        world.insert_resource(Time::<()>::default());
+        world.init_resource::<Events<FoodPicked>>();
+        world
+    }
+
+    fn pick(world: &mut World, player: Entity, kind: FoodKind) {
+        world.resource_mut::<Events<FoodPicked>>().clear();
+        world.send_event(FoodPicked { player, kind });
+    }
+
+    fn land(world: &World, (col, row): (i32, i32)) -> LandType {
+        world.resource::<GameMap>().get(col, row).cloned().unwrap()
+    }
+
+    #[test]
+    fn home_ring_cells() {
+        let world = world();
+        let mut ring = home_ring(world.resource::<GameMap>(), 24.);
+        ring.sort();
+        assert_eq!(ring, vec![(1, 3), (1, 4), (1, 5), (2, 3), (3, 3), (4, 3), (4, 4), (4, 5)]);
+        assert!(!ring.contains(&HOME));
+    }
+
+    #[test]
+    fn fortify_and_restore() {
+        let mut world = world();
+        let player = world.spawn_empty().id();
+        //有坦克压着的格子不换成铁块
+        world.spawn((Transform::from_xyz(48., -48., 0.), Moving { speed: 0., direction: GameDirection::Up, slide: 0. }));
+        pick(&mut world, player, FoodKind::Iron);
+        world.run_system_once(food_iron);
+        assert!(land(&world, (1, 3)) == LandType::Iron);
+        assert!(land(&world, (1, 5)) == LandType::Iron);
+        assert!(land(&world, (4, 4)) == LandType::None);
+        assert!(land(&world, (4, 5)) == LandType::None);
+        assert!(land(&world, HOME) == LandType::None);
+
+        //第二个铁锹补上被打掉的铁块, 原来的地形不变
+        world.resource_mut::<GameMap>().set(1, 3, LandType::None);
+        pick(&mut world, player, FoodKind::Iron);
+        world.run_system_once(food_iron);
+        assert!(land(&world, (1, 3)) == LandType::Iron);
+        assert_eq!(world.resource::<Fortify>().cells.len(), 6);
+
+        let fortify = world.resource::<GameConfig>().food.fortify;
+        world.resource_mut::<Time>().advance_by(Duration::from_secs_f32(fortify));
+        world.run_system_once(fortify_update);
+        assert!(land(&world, (1, 3)) == LandType::Brick(BRICK_FULL));
+        assert!(land(&world, (1, 5)) == LandType::Water);
+        assert!(land(&world, (2, 3)) == LandType::None);
+        assert!(world.get_resource::<Fortify>().is_none());
+    }
+
+    #[test]
+    fn tier_capped() {
+        let mut world = world();
+        let player = world.spawn(TankTier(GAME_MAX_TANK_TIER - 1)).id();
+        for _ in 0..2 {
+            pick(&mut world, player, FoodKind::Star);
+            world.run_system_once(food_star);
+        }
+        assert_eq!(world.get::<TankTier>(player).unwrap().0, GAME_MAX_TANK_TIER);
+        let player = world.spawn(TankTier(1)).id();
+        pick(&mut world, player, FoodKind::Gun);
+        world.run_system_once(food_gun);
+        assert_eq!(world.get::<TankTier>(player).unwrap().0, GAME_MAX_TANK_TIER);
+    }
+}
@@ -0,0 +1,330 @@
+use std::collections::{HashSet, VecDeque};
+use std::fmt;
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+use crate::enemy::EnemyKind;
+use crate::load::{BRICK_FULL, GameMap, LandType};
+
+//当前关卡文件的版本号, 格式变化时加一
+pub const LEVEL_VERSION: u32 = 1;
+
+//关卡文件里的原始内容, 检查通过后才转换成GameLevel
+//地形每行一个字符串, 第0行在最上方, 每个字符是一格:
+//. 空地  B 整块砖  1~9a~e 部分砖块(十六进制, 每一位对应一个四分之一格)  S 铁块  I 冰面  T 树  W 水  G 草地
+#[derive(Deserialize, Serialize)]
+struct LevelFile {
+    version: u32,
+    size: [usize; 2],
+    home: Vec3,
+    player1: Vec3,
+    player2: Vec3,
+    spawn_points: Vec<Vec3>,
+    roster: Vec<EnemyKind>,
+    tiles: Vec<String>,
+}
+
+//一关的全部数据, 坐标和配置一样以格子为单位
+#[derive(Deserialize, Asset, TypePath, Resource, Clone)]
+#[serde(try_from = "LevelFile")]
+pub struct GameLevel {
+    pub map: GameMap,
+    pub player1: Vec3,
+    pub player2: Vec3,
+    pub spawn_points: Vec<Vec3>,
+    pub roster: Vec<EnemyKind>,
+}
+
+//战役里按顺序排列的所有关卡
+#[derive(Resource)]
+pub struct Campaign {
+    pub levels: Vec<GameLevel>,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Version(u32),
+    Rows { expected: usize, found: usize },
+    Columns { row: usize, expected: usize, found: usize },
+    Tile { row: usize, col: usize, code: char },
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LevelError::Version(version) => write!(f, "unsupported level version {}, expected {}", version, LEVEL_VERSION),
+            LevelError::Rows { expected, found } => write!(f, "level has {} tile rows, expected {}", found, expected),
+            LevelError::Columns { row, expected, found } => write!(f, "tile row {} has {} columns, expected {}", row, found, expected),
+            LevelError::Tile { row, col, code } => write!(f, "unknown tile code '{}' at row {}, column {}", code, row, col),
+        }
+    }
+}
+
+impl std::error::Error for LevelError {}
+
+fn land_from_code(code: char) -> Option<LandType> {
+    match code {
+        '.' => Some(LandType::None),
+        'B' => Some(LandType::Brick(BRICK_FULL)),
+        'S' => Some(LandType::Iron),
+        'I' => Some(LandType::Ice),
+        'T' => Some(LandType::Tree),
+        'W' => Some(LandType::Water),
+        'G' => Some(LandType::Grass),
+        '1'..='9' | 'a'..='e' => code.to_digit(16).map(|quarters| LandType::Brick(quarters as u8)),
+        _ => None,
+    }
+}
+
+fn land_to_code(land: &LandType) -> char {
+    match land {
+        LandType::Brick(BRICK_FULL) => 'B',
+        LandType::Brick(quarters) => char::from_digit(*quarters as u32, 16).unwrap_or('.'),
+        LandType::Iron => 'S',
+        LandType::Ice => 'I',
+        LandType::Tree => 'T',
+        LandType::Water => 'W',
+        LandType::Grass => 'G',
+        LandType::None => '.',
+    }
+}
+
+impl TryFrom<LevelFile> for GameLevel {
+    type Error = LevelError;
+
+    fn try_from(file: LevelFile) -> Result<Self, Self::Error> {
+        if file.version != LEVEL_VERSION {
+            return Err(LevelError::Version(file.version));
+        }
+        let [cols, rows] = file.size;
+        if file.tiles.len() != rows {
+            return Err(LevelError::Rows { expected: rows, found: file.tiles.len() });
+        }
+        let mut map = GameMap::new(Vec2::new(cols as f32, rows as f32), file.home);
+        for (row, line) in file.tiles.iter().enumerate() {
+            let found = line.chars().count();
+            if found != cols {
+                return Err(LevelError::Columns { row, expected: cols, found });
+            }
+            for (col, code) in line.chars().enumerate() {
+                let land = land_from_code(code).ok_or(LevelError::Tile { row, col, code })?;
+                map.set(col as i32, row as i32, land);
+            }
+        }
+        Ok(GameLevel {
+            map,
+            player1: file.player1,
+            player2: file.player2,
+            spawn_points: file.spawn_points,
+            roster: file.roster,
+        })
+    }
+}
+
+impl From<&GameLevel> for LevelFile {
+    fn from(level: &GameLevel) -> Self {
+        LevelFile {
+            version: LEVEL_VERSION,
+            size: [level.map.size.x as usize, level.map.size.y as usize],
+            home: level.map.home,
+            player1: level.player1,
+            player2: level.player2,
+            spawn_points: level.spawn_points.clone(),
+            roster: level.roster.clone(),
+            tiles: level.map.data.iter().map(|line| line.iter().map(land_to_code).collect()).collect(),
+        }
+    }
+}
+
+impl GameLevel {
+    pub fn to_json(&self) -> serde_json::Result<String> {
+        serde_json::to_string_pretty(&LevelFile::from(self))
+    }
+
+    //和资源加载器一样经过版本和地形检查
+    pub fn from_json(json: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(json)
+    }
+}
+
+//关卡检查发现的问题和相关的格子
+pub struct LevelProblem {
+    pub message: String,
+    pub cells: Vec<(i32, i32)>,
+}
+
+//以左上角格子表示的2x2格子
+fn block_cells((col, row): (i32, i32)) -> [(i32, i32); 4] {
+    [(col, row), (col + 1, row), (col, row + 1), (col + 1, row + 1)]
+}
+
+impl GameLevel {
+    //坦克或基地占的2x2格子的左上角
+    fn block_origin(&self, position: &Vec3) -> (i32, i32) {
+        ((position.x + self.map.size.x / 2.).floor() as i32 - 1, (self.map.size.y / 2. - position.y).floor() as i32 - 1)
+    }
+
+    //敌人开得过去的格子, 砖块能打掉也算
+    fn drivable(&self, col: i32, row: i32) -> bool {
+        matches!(self.map.get(col, row), Some(land) if !matches!(land, LandType::Iron | LandType::Water))
+    }
+
+    //从出生点按格子走到紧挨着基地的位置
+    fn reaches_home(&self, start: (i32, i32), home: &[(i32, i32); 4]) -> bool {
+        let mut visited = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some((col, row)) = queue.pop_front() {
+            for next in [(col - 1, row), (col + 1, row), (col, row - 1), (col, row + 1)] {
+                let cells = block_cells(next);
+                if cells.iter().any(|cell| home.contains(cell)) {
+                    return true;
+                }
+                if cells.iter().all(|&(col, row)| self.drivable(col, row)) && visited.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        false
+    }
+
+    //基地要在地图里并且没有被地形盖住, 出生点不能压在实心地形上, 每个敌人出生点都要有路通到基地
+    pub fn problems(&self) -> Vec<LevelProblem> {
+        let mut problems = Vec::new();
+        let home = block_cells(self.block_origin(&self.map.home));
+        if home.iter().any(|&(col, row)| self.map.get(col, row).is_none()) {
+            problems.push(LevelProblem { message: "home is outside the map".to_string(), cells: Vec::new() });
+            return problems;
+        }
+        let covered: Vec<_> = home.iter().copied().filter(|&(col, row)| self.map.get(col, row) != Some(&LandType::None)).collect();
+        if !covered.is_empty() {
+            problems.push(LevelProblem { message: "tiles are painted over the home".to_string(), cells: covered });
+        }
+        if self.spawn_points.is_empty() {
+            problems.push(LevelProblem { message: "there are no enemy spawn points".to_string(), cells: Vec::new() });
+        }
+        let players = [("player 1 start".to_string(), &self.player1, false), ("player 2 start".to_string(), &self.player2, false)];
+        let spawns = self.spawn_points.iter().enumerate().map(|(index, position)| (format!("enemy spawn {}", index + 1), position, true));
+        for (name, position, is_spawn) in players.into_iter().chain(spawns) {
+            let origin = self.block_origin(position);
+            let cells = block_cells(origin);
+            if cells.iter().any(|&(col, row)| self.map.get(col, row).is_none()) {
+                problems.push(LevelProblem { message: format!("{} is outside the map", name), cells: Vec::new() });
+                continue;
+            }
+            let blocked: Vec<_> = cells.iter().copied()
+                .filter(|&(col, row)| home.contains(&(col, row)) || self.map.get(col, row).is_some_and(LandType::is_solid))
+                .collect();
+            if !blocked.is_empty() {
+                problems.push(LevelProblem { message: format!("{} overlaps solid tiles", name), cells: blocked });
+            } else if is_spawn && !self.reaches_home(origin, &home) {
+                problems.push(LevelProblem { message: format!("{} has no path to the home", name), cells: cells.to_vec() });
+            }
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //6x6的小地图, 基地在最下面中间, 出生点都在最上面两行
+    fn level_file(tiles: &[&str]) -> LevelFile {
+        LevelFile {
+            version: LEVEL_VERSION,
+            size: [6, 6],
+            home: Vec3::new(0., -2., 1.),
+            player1: Vec3::new(0., 2., 1.),
+            player2: Vec3::new(2., 2., 1.),
+            spawn_points: vec![Vec3::new(-2., 2., 1.)],
+            roster: vec![EnemyKind::Basic, EnemyKind::Armor],
+            tiles: tiles.iter().map(|line| line.to_string()).collect(),
+        }
+    }
+
+    const OPEN: [&str; 6] = ["......", "......", "BSIT..", "WG1e..", "......", "......"];
+
+    #[test]
+    fn round_trip_json() {
+        let level = GameLevel::try_from(level_file(&OPEN)).unwrap();
+        let json = level.to_json().unwrap();
+        let loaded = GameLevel::from_json(&json).unwrap();
+        assert!(loaded.map.data == level.map.data);
+        assert_eq!(loaded.map.home, level.map.home);
+        assert_eq!(loaded.spawn_points, level.spawn_points);
+        assert_eq!(loaded.roster, level.roster);
+        assert_eq!(loaded.to_json().unwrap(), json);
+        assert!(loaded.map.get(0, 2) == Some(&LandType::Brick(BRICK_FULL)));
+        assert!(loaded.map.get(3, 3) == Some(&LandType::Brick(0xe)));
+    }
+
+    #[test]
+    fn bad_tile_code() {
+        let tiles = ["......", "..X...", "......", "......", "......", "......"];
+        assert!(matches!(GameLevel::try_from(level_file(&tiles)), Err(LevelError::Tile { row: 1, col: 2, code: 'X' })));
+    }
+
+    #[test]
+    fn wrong_row_count() {
+        assert!(matches!(GameLevel::try_from(level_file(&OPEN[..5])), Err(LevelError::Rows { expected: 6, found: 5 })));
+    }
+
+    #[test]
+    fn wrong_column_count() {
+        let tiles = ["......", "......", ".......", "......", "......", "......"];
+        assert!(matches!(GameLevel::try_from(level_file(&tiles)), Err(LevelError::Columns { row: 2, expected: 6, found: 7 })));
+    }
+
+    //关卡检查: 1P在第2,3列, 2P在第4,5列, 出生点在第0,1列, 都在最上面两行, 基地在最下面两行的第2,3列
+    fn problems(level: LevelFile) -> Vec<(String, Vec<(i32, i32)>)> {
+        GameLevel::try_from(level).unwrap().problems().into_iter().map(|problem| (problem.message, problem.cells)).collect()
+    }
+
+    #[test]
+    fn open_map_has_no_problems() {
+        assert!(problems(level_file(&OPEN)).is_empty());
+    }
+
+    #[test]
+    fn walled_in_home() {
+        let tiles = ["......", "......", "......", ".SSSS.", ".S..S.", ".S..S."];
+        assert_eq!(problems(level_file(&tiles)), vec![("enemy spawn 1 has no path to the home".to_string(), vec![(0, 0), (1, 0), (0, 1), (1, 1)])]);
+    }
+
+    #[test]
+    fn start_on_solid_tiles() {
+        let tiles = ["...B..", "......", "......", "......", "......", "....W."];
+        assert_eq!(problems(level_file(&tiles)), vec![("player 1 start overlaps solid tiles".to_string(), vec![(3, 0)])]);
+    }
+
+    #[test]
+    fn tiles_over_home() {
+        let tiles = ["......", "......", "......", "......", "..T...", "...I.."];
+        assert_eq!(problems(level_file(&tiles)), vec![("tiles are painted over the home".to_string(), vec![(2, 4), (3, 5)])]);
+    }
+
+    #[test]
+    fn point_outside_map() {
+        let mut level = level_file(&OPEN);
+        level.player2 = Vec3::new(3., 2., 1.);
+        assert_eq!(problems(level), vec![("player 2 start is outside the map".to_string(), Vec::new())]);
+        let mut level = level_file(&OPEN);
+        level.home = Vec3::new(0., -3., 1.);
+        assert_eq!(problems(level), vec![("home is outside the map".to_string(), Vec::new())]);
+    }
+
+    #[test]
+    fn shipped_levels_have_no_problems() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/levels");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if !path.to_string_lossy().ends_with(".level.json") {
+                continue;
+            }
+            let level = GameLevel::from_json(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            assert!(level.problems().is_empty(), "{} has problems", path.display());
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+}
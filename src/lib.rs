@@ -1,12 +1,27 @@
 mod load;
+mod level;
+mod bullet;
+mod enemy;
+mod effect;
+mod food;
+mod score;
+mod stage;
 mod world;
 mod utils;
 mod ui;
 
 use bevy::prelude::*;
+use crate::bullet::BulletPlugin;
+use crate::effect::EffectPlugin;
+use crate::enemy::EnemyPlugin;
+use crate::food::FoodPlugin;
 use crate::load::LoadPlugin;
+use crate::score::ScorePlugin;
+use crate::stage::StagePlugin;
 use crate::ui::editor::EditorPlugin;
+use crate::ui::map_file::MapFilePlugin;
 use crate::ui::menu::MenuPlugin;
+use crate::ui::pause::PausePlugin;
 use crate::world::WorldPlugin;
 
 pub struct GamePlugin;
@@ -21,9 +36,21 @@ enum GameState {
     Menu,
 }
 
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
+enum PlayingState {
+    Intro,
+    Running,
+    Paused,
+    Cleared,
+    GameOver,
+    Tally,
+    #[default]
+    Disabled,
+}
+
 impl Plugin for GamePlugin {
     fn build(&self, app: &mut App) {
-        app.add_state::<GameState>().add_plugins(
+        app.add_state::<GameState>().add_state::<PlayingState>().add_plugins(
             (DefaultPlugins.set(
                 WindowPlugin {
                     primary_window: Some(Window {
@@ -33,7 +60,7 @@ impl Plugin for GamePlugin {
                     }),
                     ..default()
                 }
-            ), LoadPlugin, WorldPlugin, MenuPlugin, EditorPlugin)
+            ), LoadPlugin, WorldPlugin, BulletPlugin, EnemyPlugin, FoodPlugin, EffectPlugin, StagePlugin, ScorePlugin, MenuPlugin, EditorPlugin, MapFilePlugin, PausePlugin)
         );
     }
 }
\ No newline at end of file
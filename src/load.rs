@@ -1,9 +1,16 @@
+use bevy::asset::LoadState;
+use bevy::asset::io::file::FileAssetReader;
 use bevy::prelude::*;
 use bevy_asset_loader::asset_collection::AssetCollection;
 use bevy_asset_loader::prelude::*;
+use std::collections::HashMap;
+use std::fs;
 use serde::Deserialize;
 use crate::GameState;
-use crate::world::Moving;
+use crate::enemy::EnemyKind;
+use crate::food::FoodKind;
+use crate::level::{Campaign, GameLevel};
+use crate::world::{GameDirection, Moving};
 use bevy_common_assets::json::JsonAssetPlugin;
 use crate::utils::Boundary;
 
@@ -17,9 +24,10 @@ impl Plugin for LoadPlugin {
             .add_dynamic_collection_to_loading_state::<_, StandardDynamicAssetCollection>(GameState::LoadTexture, "load.assets.ron")
             .add_collection_to_loading_state::<_, GameTexture>(GameState::LoadTexture)
             .add_plugins(JsonAssetPlugin::<GameConfig>::new(&["json"]))
+            .add_plugins(JsonAssetPlugin::<GameLevel>::new(&["level.json"]))
             .insert_resource(Msaa::Off)
             .add_systems(Startup, load_game_config)
-            .add_systems(Update, insert_resource_game_config.run_if(in_state(GameState::LoadConfig)))
+            .add_systems(Update, (insert_resource_game_config, load_campaign, insert_resource_campaign, campaign_load_failed, load_config_finished).run_if(in_state(GameState::LoadConfig)))
         ;
     }
 }
@@ -29,10 +37,78 @@ fn load_game_config(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands.insert_resource(json);
 }
 
+//关卡列表在配置里, 配置加载完后再加载每一关
+fn load_campaign(mut commands: Commands, asset_server: Res<AssetServer>, game_config: Option<Res<GameConfig>>, campaign: Option<Res<CampaignHandle>>) {
+    if let (Some(game_config), None) = (game_config, campaign) {
+        let handles = game_config.campaign.iter().map(|path| (path.clone(), asset_server.load(path.clone()))).collect();
+        commands.insert_resource(CampaignHandle(handles));
+    }
+}
+
 //将可能还在加载的game_config加入到resource池子中
-fn insert_resource_game_config(mut commands: Commands, game_config: Res<GameConfigHandle>, mut game_configs: ResMut<Assets<GameConfig>>, mut state: ResMut<NextState<GameState>>) {
+fn insert_resource_game_config(mut commands: Commands, game_config: Res<GameConfigHandle>, mut game_configs: ResMut<Assets<GameConfig>>) {
     if let Some(game_config) = game_configs.remove(game_config.0.id()) {
         commands.insert_resource(game_config);
+    }
+}
+
+//所有关卡都加载完后按顺序放进战役, 第一关同时作为默认关卡
+fn insert_resource_campaign(mut commands: Commands, handles: Option<Res<CampaignHandle>>, game_levels: Res<Assets<GameLevel>>, campaign: Option<Res<Campaign>>) {
+    let (Some(handles), None) = (handles, campaign) else {
+        return;
+    };
+    if !handles.0.iter().all(|(_, handle)| game_levels.contains(handle.id())) {
+        return;
+    }
+    let levels: Vec<GameLevel> = handles.0.iter().filter_map(|(_, handle)| game_levels.get(handle.id()).cloned()).collect();
+    match levels.first() {
+        Some(level) => commands.insert_resource(level.clone()),
+        None => error!("campaign in resource.json has no levels"),
+    }
+    commands.insert_resource(Campaign { levels });
+}
+
+//关卡文件有误时资源加载器只在日志里输出一行, 游戏会一直停在加载中
+//这里找出加载失败的关卡, 重新解析一遍拿到出错原因, 显示是哪个文件出了什么错
+fn campaign_load_failed(mut commands: Commands, asset_server: Res<AssetServer>, handles: Option<Res<CampaignHandle>>, mut reported: Local<bool>) {
+    let Some(handles) = handles else {
+        return;
+    };
+    if *reported {
+        return;
+    }
+    let errors: Vec<String> = handles.0.iter()
+        .filter(|(_, handle)| asset_server.get_load_state(handle.id()) == Some(LoadState::Failed))
+        .map(|(path, _)| format!("failed to load {}: {}", path, level_error(path)))
+        .collect();
+    if errors.is_empty() {
+        return;
+    }
+    *reported = true;
+    for message in errors.iter() {
+        error!("{}", message);
+    }
+    commands.spawn(TextBundle::from_section(errors.join("\n"), TextStyle {
+        font_size: 20.0,
+        color: Color::rgb(0.9, 0.3, 0.2),
+        ..Default::default()
+    }).with_style(Style {
+        margin: UiRect::all(Val::Px(20.)),
+        ..Default::default()
+    }));
+}
+
+fn level_error(path: &str) -> String {
+    let file = FileAssetReader::get_base_path().join("assets").join(path);
+    match fs::read_to_string(file) {
+        Ok(json) => GameLevel::from_json(&json).err().map_or("unknown error".to_string(), |err| err.to_string()),
+        Err(err) => err.to_string(),
+    }
+}
+
+//配置和关卡都加载完后进入菜单
+fn load_config_finished(game_config: Option<Res<GameConfig>>, game_level: Option<Res<GameLevel>>, mut state: ResMut<NextState<GameState>>) {
+    if game_config.is_some() && game_level.is_some() {
         state.set(GameState::Menu)
     }
 }
@@ -40,28 +116,170 @@ fn insert_resource_game_config(mut commands: Commands, game_config: Res<GameConf
 #[derive(Resource)]
 pub struct GameConfigHandle(pub Handle<GameConfig>);
 
-#[derive(Clone)]
+#[derive(Resource)]
+pub struct CampaignHandle(pub Vec<(String, Handle<GameLevel>)>);
+
+#[derive(Clone, Resource)]
 pub struct GameMap {
     pub size: Vec2,
     pub data: Vec<Vec<LandType>>,
+    //基地位置, 和配置里的坐标一样以格子为单位
+    pub home: Vec3,
 }
 
-#[derive(Clone)]
+pub const BRICK_FULL: u8 = 0b1111;
+
+#[derive(Clone, PartialEq)]
 pub enum LandType {
     Tree,
     Ice,
-    Brick,
+    //砖块按四分之一格记录, 每一位对应一块: 0左上 1右上 2左下 3右下
+    Brick(u8),
     Iron,
     Water,
     Grass,
     None,
 }
 
+//地图上的一块地形, 砖块会拆成四分之一格
+pub struct LandPart {
+    pub col: i32,
+    pub row: i32,
+    pub quarter: Option<u8>,
+    pub rect: Rect,
+}
+
+impl LandType {
+    //坦克无法通过的地形
+    pub fn is_solid(&self) -> bool {
+        matches!(self, LandType::Brick(_) | LandType::Iron | LandType::Water)
+    }
+
+    //会挡住子弹的地形
+    pub fn stops_bullet(&self) -> bool {
+        matches!(self, LandType::Brick(_) | LandType::Iron)
+    }
+
+    //草地没有单独的贴图, 用树的贴图染成浅绿色
+    pub fn tint(&self) -> Color {
+        match self {
+            LandType::Grass => Color::rgb(0.6, 1., 0.4),
+            _ => Color::WHITE,
+        }
+    }
+}
+
+impl GameMap {
+    pub fn new(size: Vec2, home: Vec3) -> Self {
+        GameMap {
+            size,
+            data: vec![vec![LandType::None; size.x as usize]; size.y as usize],
+            home,
+        }
+    }
+
+    //基地占2x2格子
+    pub fn home_rect(&self, step: f32) -> Rect {
+        Rect::from_center_size(self.home.truncate() * step, Vec2::splat(step * 2.))
+    }
+
+    //世界坐标转换为地图格子坐标(列, 行), 第0行在最上方
+    pub fn to_cell(&self, pos: Vec2, step: f32) -> (i32, i32) {
+        ((pos.x / step + self.size.x / 2.).floor() as i32, (self.size.y / 2. - pos.y / step).floor() as i32)
+    }
+
+    pub fn cell_rect(&self, col: i32, row: i32, step: f32) -> Rect {
+        let min = Vec2::new(col as f32 - self.size.x / 2., self.size.y / 2. - row as f32 - 1.) * step;
+        Rect::from_corners(min, min + Vec2::splat(step))
+    }
+
+    pub fn quarter_rect(&self, col: i32, row: i32, quarter: u8, step: f32) -> Rect {
+        let cell = self.cell_rect(col, row, step);
+        let half = step / 2.;
+        let min = Vec2::new(cell.min.x + (quarter % 2) as f32 * half, cell.max.y - (quarter / 2 + 1) as f32 * half);
+        Rect::from_corners(min, min + Vec2::splat(half))
+    }
+
+    pub fn get(&self, col: i32, row: i32) -> Option<&LandType> {
+        if col < 0 || row < 0 {
+            return None;
+        }
+        self.data.get(row as usize).and_then(|line| line.get(col as usize))
+    }
+
+    pub fn set(&mut self, col: i32, row: i32, land: LandType) {
+        if self.get(col, row).is_some() {
+            self.data[row as usize][col as usize] = land;
+        }
+    }
+
+    //区域覆盖到的地图内格子(列, 行), 区域边缘刚好贴着时不算覆盖
+    pub fn cells(&self, rect: Rect, step: f32) -> Vec<(i32, i32)> {
+        let inner = rect.inset(-0.01);
+        let (min_col, min_row) = self.to_cell(Vec2::new(inner.min.x, inner.max.y), step);
+        let (max_col, max_row) = self.to_cell(Vec2::new(inner.max.x, inner.min.y), step);
+        (min_row..=max_row)
+            .flat_map(|row| (min_col..=max_col).map(move |col| (col, row)))
+            .filter(|&(col, row)| self.get(col, row).is_some())
+            .collect()
+    }
+
+    //区域内与之重叠的所有地形
+    pub fn parts(&self, rect: Rect, step: f32) -> Vec<(LandPart, &LandType)> {
+        let mut parts = Vec::new();
+        for (col, row) in self.cells(rect, step) {
+            match self.get(col, row) {
+                Some(land @ LandType::Brick(quarters)) => {
+                    for quarter in 0..4 {
+                        let part = self.quarter_rect(col, row, quarter, step);
+                        if quarters & (1 << quarter) != 0 && !part.intersect(rect).is_empty() {
+                            parts.push((LandPart { col, row, quarter: Some(quarter), rect: part }, land));
+                        }
+                    }
+                }
+                Some(LandType::None) | None => {}
+                Some(land) => parts.push((LandPart { col, row, quarter: None, rect: self.cell_rect(col, row, step) }, land)),
+            }
+        }
+        parts
+    }
+
+    //基地和地形一样挡住坦克
+    pub fn is_blocked(&self, rect: Rect, step: f32) -> bool {
+        !self.home_rect(step).intersect(rect).is_empty() || self.parts(rect, step).iter().any(|(_, land)| land.is_solid())
+    }
+
+    //坦克占地里有冰面时算在冰上
+    pub fn is_on_ice(&self, rect: Rect, step: f32) -> bool {
+        self.cells(rect, step).into_iter().any(|(col, row)| matches!(self.get(col, row), Some(LandType::Ice)))
+    }
+
+    //砖块只去掉对应的四分之一格, 其余地形整格清除
+    pub fn remove_part(&mut self, part: &LandPart) {
+        let land = &mut self.data[part.row as usize][part.col as usize];
+        match (part.quarter, &land) {
+            (Some(quarter), LandType::Brick(quarters)) => {
+                let left = quarters & !(1 << quarter);
+                *land = if left == 0 { LandType::None } else { LandType::Brick(left) };
+            }
+            _ => *land = LandType::None,
+        }
+    }
+}
+
 #[derive(Deserialize, Asset, TypePath, Resource)]
 pub struct GameConfig {
     pub world: WorldConfig,
     pub player1: PlayerConfig,
     pub player2: PlayerConfig,
+    //玩家坦克每一级的属性, 第一项是1级
+    pub tiers: Vec<TierConfig>,
+    pub enemy: EnemyConfig,
+    pub spawn: SpawnConfig,
+    pub food: FoodConfig,
+    pub stage: StageConfig,
+    //战役的关卡文件, 按顺序游玩
+    pub campaign: Vec<String>,
 }
 
 #[derive(Deserialize)]
@@ -69,12 +287,74 @@ pub struct WorldConfig {
     pub size: Vec3,
     pub step: f32,
     pub boundary: Boundary,
+    //松开方向键后在冰面上继续滑行的距离
+    pub ice_slide: f32,
 }
 
 #[derive(Deserialize)]
 pub struct PlayerConfig {
     pub moving: Moving,
-    pub position: Vec3,
+    pub lives: u32,
+}
+
+#[derive(Deserialize)]
+pub struct TierConfig {
+    pub bullet_speed: f32,
+    pub bullets: usize,
+    pub break_iron: bool,
+}
+
+impl GameConfig {
+    pub fn tier(&self, tier: u8) -> &TierConfig {
+        &self.tiers[(tier.max(1) as usize - 1).min(self.tiers.len() - 1)]
+    }
+}
+
+#[derive(Deserialize)]
+pub struct EnemyConfig {
+    pub max_alive: usize,
+    pub spawn_interval: f32,
+    //出场顺序里携带道具的敌人序号, 从0开始
+    pub bonus: Vec<usize>,
+    pub kinds: HashMap<EnemyKind, EnemyKindConfig>,
+}
+
+#[derive(Deserialize)]
+pub struct EnemyKindConfig {
+    pub speed: f32,
+    pub health: u32,
+    pub bullet_speed: f32,
+    pub fire_interval: f32,
+    //0~1, 换方向时朝基地或玩家前进的概率
+    pub aggression: f32,
+    //玩家消灭一辆得到的分数
+    pub points: u32,
+}
+
+//坦克出场闪光和玩家出生护盾的时间, 单位秒
+#[derive(Deserialize)]
+pub struct SpawnConfig {
+    pub appear: f32,
+    pub shield: f32,
+}
+
+//关卡开始, 过关, 游戏结束和结算画面停留的秒数
+#[derive(Deserialize)]
+pub struct StageConfig {
+    pub intro: f32,
+    pub cleared: f32,
+    pub game_over: f32,
+    pub tally: f32,
+}
+
+//道具相关的时间, 单位秒
+#[derive(Deserialize)]
+pub struct FoodConfig {
+    pub lifetime: f32,
+    pub blink: f32,
+    pub freeze: f32,
+    pub fortify: f32,
+    pub shield: f32,
 }
 
 #[derive(AssetCollection, Resource)]
@@ -91,6 +371,38 @@ pub struct GameTexture {
     pub p2_2: Handle<TextureAtlas>,
     #[asset(key = "player2_l")]
     pub p2_3: Handle<TextureAtlas>,
+    #[asset(key = "enemy_1_0")]
+    pub enemy_1_0: Handle<TextureAtlas>,
+    #[asset(key = "enemy_1_1")]
+    pub enemy_1_1: Handle<TextureAtlas>,
+    #[asset(key = "enemy_1_2")]
+    pub enemy_1_2: Handle<TextureAtlas>,
+    #[asset(key = "enemy_1_3")]
+    pub enemy_1_3: Handle<TextureAtlas>,
+    #[asset(key = "enemy_2_0")]
+    pub enemy_2_0: Handle<TextureAtlas>,
+    #[asset(key = "enemy_2_1")]
+    pub enemy_2_1: Handle<TextureAtlas>,
+    #[asset(key = "enemy_2_2")]
+    pub enemy_2_2: Handle<TextureAtlas>,
+    #[asset(key = "enemy_2_3")]
+    pub enemy_2_3: Handle<TextureAtlas>,
+    #[asset(key = "enemy_3_0")]
+    pub enemy_3_0: Handle<TextureAtlas>,
+    #[asset(key = "enemy_3_1")]
+    pub enemy_3_1: Handle<TextureAtlas>,
+    #[asset(key = "enemy_3_2")]
+    pub enemy_3_2: Handle<TextureAtlas>,
+    #[asset(key = "enemy_3_3")]
+    pub enemy_3_3: Handle<TextureAtlas>,
+    #[asset(key = "enemy_4_0")]
+    pub enemy_4_0: Handle<TextureAtlas>,
+    #[asset(key = "enemy_4_1")]
+    pub enemy_4_1: Handle<TextureAtlas>,
+    #[asset(key = "enemy_4_2")]
+    pub enemy_4_2: Handle<TextureAtlas>,
+    #[asset(key = "enemy_4_3")]
+    pub enemy_4_3: Handle<TextureAtlas>,
     #[asset(key = "world")]
     pub world: Handle<TextureAtlas>,
     #[asset(key = "logo")]
@@ -101,9 +413,119 @@ pub struct GameTexture {
     pub tree: Handle<TextureAtlas>,
     #[asset(key = "ice")]
     pub ice: Handle<TextureAtlas>,
+    #[asset(key = "river1")]
+    pub river1: Handle<TextureAtlas>,
+    #[asset(key = "river2")]
+    pub river2: Handle<TextureAtlas>,
     #[asset(key = "brick")]
     pub brick: Handle<TextureAtlas>,
+    #[asset(key = "brick_quarter")]
+    pub brick_quarter: Handle<TextureAtlas>,
     #[asset(key = "iron")]
     pub iron: Handle<TextureAtlas>,
+    #[asset(key = "home")]
+    pub home: Handle<TextureAtlas>,
+    #[asset(key = "home_destroyed")]
+    pub home_destroyed: Handle<TextureAtlas>,
+    #[asset(key = "food_star")]
+    pub food_star: Handle<TextureAtlas>,
+    #[asset(key = "food_tank")]
+    pub food_tank: Handle<TextureAtlas>,
+    #[asset(key = "food_clock")]
+    pub food_clock: Handle<TextureAtlas>,
+    #[asset(key = "food_boom")]
+    pub food_boom: Handle<TextureAtlas>,
+    #[asset(key = "food_iron")]
+    pub food_iron: Handle<TextureAtlas>,
+    #[asset(key = "food_protect")]
+    pub food_protect: Handle<TextureAtlas>,
+    #[asset(key = "food_gun")]
+    pub food_gun: Handle<TextureAtlas>,
+    #[asset(key = "boom_static")]
+    pub boom_static: Handle<TextureAtlas>,
+    #[asset(key = "boom_dynamic")]
+    pub boom_dynamic: Handle<TextureAtlas>,
+    #[asset(key = "gameover")]
+    pub gameover: Handle<TextureAtlas>,
+    #[asset(key = "appear")]
+    pub appear: Handle<TextureAtlas>,
+    #[asset(key = "protect")]
+    pub protect: Handle<TextureAtlas>,
+    #[asset(key = "bullet_up")]
+    pub bullet_up: Handle<TextureAtlas>,
+    #[asset(key = "bullet_down")]
+    pub bullet_down: Handle<TextureAtlas>,
+    #[asset(key = "bullet_left")]
+    pub bullet_left: Handle<TextureAtlas>,
+    #[asset(key = "bullet_right")]
+    pub bullet_right: Handle<TextureAtlas>,
+}
+
+impl GameTexture {
+    pub fn bullet(&self, direction: &GameDirection) -> Handle<TextureAtlas> {
+        match direction {
+            GameDirection::Up => self.bullet_up.clone(),
+            GameDirection::Down => self.bullet_down.clone(),
+            GameDirection::Left => self.bullet_left.clone(),
+            GameDirection::Right => self.bullet_right.clone(),
+        }
+    }
+
+    pub fn player(&self, player1: bool, tier: u8) -> Handle<TextureAtlas> {
+        match (player1, tier) {
+            (true, 0..=1) => self.p1_1.clone(),
+            (true, 2) => self.p1_2.clone(),
+            (true, _) => self.p1_3.clone(),
+            (false, 0..=1) => self.p2_1.clone(),
+            (false, 2) => self.p2_2.clone(),
+            (false, _) => self.p2_3.clone(),
+        }
+    }
+
+    //水面两帧交替
+    pub fn river(&self, frame: usize) -> Handle<TextureAtlas> {
+        [&self.river1, &self.river2][frame % 2].clone()
+    }
+
+    //一格地形用到的贴图和图块序号, 带上画在哪个四分之一格, 整格一张的是None
+    //砖块每个四分之一格用砖块图的一角, 冰, 树和水面的贴图只有半格大, 四个四分之一格各画一张, 不拉伸
+    pub fn land(&self, land: &LandType, river_frame: usize) -> Vec<(Handle<TextureAtlas>, usize, Option<u8>)> {
+        let half = match land {
+            LandType::Brick(quarters) => {
+                return (0..4u8).filter(|quarter| quarters & (1 << quarter) != 0)
+                    .map(|quarter| (self.brick_quarter.clone(), quarter as usize, Some(quarter)))
+                    .collect();
+            }
+            LandType::Iron => return vec![(self.iron.clone(), 0, None)],
+            LandType::None => return Vec::new(),
+            LandType::Ice => self.ice.clone(),
+            LandType::Tree | LandType::Grass => self.tree.clone(),
+            LandType::Water => self.river(river_frame),
+        };
+        (0..4u8).map(|quarter| (half.clone(), 0, Some(quarter))).collect()
+    }
+
+    pub fn food(&self, kind: &FoodKind) -> Handle<TextureAtlas> {
+        match kind {
+            FoodKind::Star => self.food_star.clone(),
+            FoodKind::Tank => self.food_tank.clone(),
+            FoodKind::Clock => self.food_clock.clone(),
+            FoodKind::Boom => self.food_boom.clone(),
+            FoodKind::Iron => self.food_iron.clone(),
+            FoodKind::Protect => self.food_protect.clone(),
+            FoodKind::Gun => self.food_gun.clone(),
+        }
+    }
+
+    //variant 0~2 为普通颜色, 3 为红色
+    pub fn enemy(&self, kind: &EnemyKind, variant: usize) -> Handle<TextureAtlas> {
+        let atlases = match kind {
+            EnemyKind::Basic => [&self.enemy_1_0, &self.enemy_1_1, &self.enemy_1_2, &self.enemy_1_3],
+            EnemyKind::Fast => [&self.enemy_2_0, &self.enemy_2_1, &self.enemy_2_2, &self.enemy_2_3],
+            EnemyKind::Power => [&self.enemy_3_0, &self.enemy_3_1, &self.enemy_3_2, &self.enemy_3_3],
+            EnemyKind::Armor => [&self.enemy_4_0, &self.enemy_4_1, &self.enemy_4_2, &self.enemy_4_3],
+        };
+        atlases[variant.min(3)].clone()
+    }
 }
 
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use bevy::prelude::*;
+use crate::{GameState, PlayingState};
+use crate::bullet::bullet_hit_enemy;
+use crate::enemy::EnemyKind;
+use crate::load::GameConfig;
+use crate::stage::Stage;
+use crate::ui::menu::full_screen_node_setup;
+use crate::world::{AnyPlayer, GameMode, Player1, leave_playing};
+
+pub struct ScorePlugin;
+
+impl Plugin for ScorePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EnemyKilled>().init_resource::<Scores>()
+            .add_systems(OnEnter(PlayingState::Tally), tally_setup)
+            .add_systems(OnExit(PlayingState::Tally), tally_cleanup)
+            //最后一辆敌人被消灭的那一帧就会转到过关, 必须在同一帧里记上分
+            .add_systems(Update, score_update.after(bullet_hit_enemy).run_if(in_state(PlayingState::Running)))
+            .add_systems(Update, tally_update.run_if(in_state(PlayingState::Tally)));
+    }
+}
+
+//结算画面里敌人的排列顺序
+const TALLY_KINDS: [EnemyKind; 4] = [EnemyKind::Basic, EnemyKind::Fast, EnemyKind::Power, EnemyKind::Armor];
+
+//玩家子弹消灭了一辆敌人, by 是开炮的玩家
+#[derive(Event)]
+pub struct EnemyKilled {
+    pub by: Entity,
+    pub kind: EnemyKind,
+}
+
+//一个玩家的总分和消灭每种敌人的数量
+#[derive(Default)]
+pub struct PlayerScore {
+    pub points: u32,
+    pub kills: HashMap<EnemyKind, u32>,
+}
+
+//两个玩家分开计分, 坦克没命了分数也留到结算
+#[derive(Default, Resource)]
+pub struct Scores {
+    pub player1: PlayerScore,
+    pub player2: PlayerScore,
+}
+
+#[derive(Component)]
+struct TallyScreen;
+
+#[derive(Resource)]
+struct TallyTimer(Timer);
+
+//按敌人类型给开炮的玩家加分
+fn score_update(mut events: EventReader<EnemyKilled>, game_config: Res<GameConfig>, mut scores: ResMut<Scores>, players: Query<Has<Player1>, AnyPlayer>) {
+    for killed in events.read() {
+        let Ok(is_player1) = players.get(killed.by) else {
+            continue;
+        };
+        let score = if is_player1 { &mut scores.player1 } else { &mut scores.player2 };
+        score.points += game_config.enemy.kinds[&killed.kind].points;
+        *score.kills.entry(killed.kind).or_default() += 1;
+    }
+}
+
+fn tally_text_setup(parent: &mut ChildBuilder, text: String, font_size: f32, color: Color) {
+    parent.spawn(TextBundle::from_section(text, TextStyle {
+        font_size,
+        color,
+        ..Default::default()
+    }).with_style(Style {
+        margin: UiRect::all(Val::Px(6.)),
+        ..Default::default()
+    }));
+}
+
+//结算画面列出每个玩家消灭每种敌人的数量和得分, 单人模式只有1P
+fn tally_setup(mut commands: Commands, scores: Res<Scores>, stage: Res<Stage>, game_mode: Res<GameMode>, game_config: Res<GameConfig>) {
+    let mut root = full_screen_node_setup();
+    root.style.position_type = PositionType::Absolute;
+    root.background_color = Color::BLACK.into();
+    root.z_index = ZIndex::Global(10);
+    let mut players = vec![("1P", &scores.player1)];
+    if *game_mode == GameMode::Double {
+        players.push(("2P", &scores.player2));
+    }
+    commands.spawn(root).insert(TallyScreen).with_children(|parent| {
+        tally_text_setup(parent, format!("STAGE {}", stage.index + 1), 30.0, Color::WHITE);
+        parent.spawn(NodeBundle::default()).with_children(|parent| {
+            for (name, score) in players {
+                parent.spawn(NodeBundle {
+                    style: Style {
+                        flex_direction: FlexDirection::Column,
+                        align_items: AlignItems::Center,
+                        margin: UiRect::horizontal(Val::Px(30.)),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                }).with_children(|parent| {
+                    tally_text_setup(parent, name.to_string(), 24.0, Color::rgb(0.9, 0.3, 0.2));
+                    for kind in TALLY_KINDS {
+                        let kills = score.kills.get(&kind).copied().unwrap_or(0);
+                        let points = game_config.enemy.kinds[&kind].points;
+                        tally_text_setup(parent, format!("{:?}  {} x {} = {}", kind, kills, points, kills * points), 20.0, Color::WHITE);
+                    }
+                    tally_text_setup(parent, format!("TOTAL {}", score.points), 24.0, Color::YELLOW);
+                });
+            }
+        });
+    });
+    commands.insert_resource(TallyTimer(Timer::from_seconds(game_config.stage.tally, TimerMode::Once)));
+}
+
+//结算画面停留一会儿后回到菜单, 试玩时由菜单转回编辑器
+fn tally_update(time: Res<Time>, mut timer: ResMut<TallyTimer>, mut game_state: ResMut<NextState<GameState>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if timer.0.tick(time.delta()).just_finished() {
+        leave_playing(&mut game_state, &mut playing_state);
+    }
+}
+
+fn tally_cleanup(mut commands: Commands, query: Query<Entity, With<TallyScreen>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
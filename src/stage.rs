@@ -0,0 +1,168 @@
+use bevy::prelude::*;
+use crate::{GameState, PlayingState};
+use crate::food::{EnemyFreeze, Fortify};
+use crate::level::Campaign;
+use crate::load::{GameConfig, GameTexture};
+use crate::ui::editor::Playtest;
+use crate::ui::menu::full_screen_node_setup;
+use crate::world::leave_playing;
+
+pub struct StagePlugin;
+
+impl Plugin for StagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Stage>()
+            .add_systems(OnEnter(PlayingState::Intro), (stage_cleanup, intro_setup))
+            .add_systems(OnEnter(PlayingState::Disabled), play_cleanup)
+            .add_systems(OnEnter(PlayingState::Cleared), cleared_setup)
+            .add_systems(OnEnter(PlayingState::GameOver), game_over_setup)
+            .add_systems(OnExit(PlayingState::Intro), curtain_cleanup)
+            .add_systems(OnExit(PlayingState::Cleared), curtain_cleanup)
+            .add_systems(Update, intro_update.run_if(in_state(PlayingState::Intro)))
+            .add_systems(Update, cleared_update.run_if(in_state(PlayingState::Cleared)))
+            .add_systems(Update, game_over_update.run_if(in_state(PlayingState::GameOver)));
+    }
+}
+
+//当前是战役里的第几关, 从0开始
+#[derive(Default, Resource)]
+pub struct Stage {
+    pub index: usize,
+}
+
+//只属于一关的实体: 地形, 基地, 敌人, 子弹, 道具和爆炸, 换关或重新开始这一关时全部销毁, 玩家坦克带到下一关
+#[derive(Component)]
+pub struct StageEntity;
+
+//整局游戏都在的实体, 也就是玩家坦克, 退出游戏时和StageEntity一起销毁
+#[derive(Component)]
+pub struct PlayEntity;
+
+#[derive(Component)]
+struct StageCurtain;
+
+//游戏结束的横幅, from 是开始上升的高度
+#[derive(Component)]
+struct GameOverBanner {
+    from: f32,
+}
+
+//关卡开始和过关画面的停留时间, 过关后有下一关时记下下一关
+#[derive(Resource)]
+struct StageTimer {
+    timer: Timer,
+    next: Option<usize>,
+}
+
+fn curtain_setup(commands: &mut Commands, background: Color, color: Color, text: String) {
+    let mut root = full_screen_node_setup();
+    root.style.position_type = PositionType::Absolute;
+    root.background_color = background.into();
+    root.z_index = ZIndex::Global(5);
+    commands.spawn(root).insert(StageCurtain).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(text, TextStyle {
+            font_size: 40.0,
+            color,
+            ..Default::default()
+        }));
+    });
+}
+
+//每关开始时灰色幕布盖住地图并显示关卡号
+fn intro_setup(mut commands: Commands, stage: Res<Stage>, game_config: Res<GameConfig>) {
+    curtain_setup(&mut commands, Color::rgb(0.46, 0.46, 0.46), Color::BLACK, format!("STAGE {}", stage.index + 1));
+    commands.insert_resource(StageTimer {
+        timer: Timer::from_seconds(game_config.stage.intro, TimerMode::Once),
+        next: None,
+    });
+}
+
+fn intro_update(time: Res<Time>, mut timer: ResMut<StageTimer>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if timer.timer.tick(time.delta()).just_finished() {
+        playing_state.set(PlayingState::Running);
+    }
+}
+
+//过关后画面停住一会儿, 打完最后一关显示胜利画面, 试玩的地图没有下一关
+fn cleared_setup(mut commands: Commands, stage: Res<Stage>, campaign: Res<Campaign>, playtest: Option<Res<Playtest>>, game_config: Res<GameConfig>) {
+    let next = Some(stage.index + 1).filter(|&next| playtest.is_none() && next < campaign.levels.len());
+    if playtest.is_none() && next.is_none() {
+        curtain_setup(&mut commands, Color::rgba(0., 0., 0., 0.8), Color::YELLOW, "VICTORY".to_string());
+    } else {
+        curtain_setup(&mut commands, Color::NONE, Color::WHITE, format!("STAGE {} CLEAR", stage.index + 1));
+    }
+    commands.insert_resource(StageTimer {
+        timer: Timer::from_seconds(game_config.stage.cleared, TimerMode::Once),
+        next,
+    });
+}
+
+//有下一关时换上下一关的地图, 否则回到菜单
+fn cleared_update(mut commands: Commands, time: Res<Time>, mut timer: ResMut<StageTimer>, campaign: Res<Campaign>, mut stage: ResMut<Stage>, mut game_state: ResMut<NextState<GameState>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if !timer.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    match timer.next {
+        Some(next) => {
+            stage.index = next;
+            commands.insert_resource(campaign.levels[next].clone());
+            playing_state.set(PlayingState::Intro);
+        }
+        None => leave_playing(&mut game_state, &mut playing_state),
+    }
+}
+
+//基地被打爆或者玩家的命都用完后, GAME OVER从地图下边升上来, 下面的画面停住
+fn game_over_setup(mut commands: Commands, game_texture: Res<GameTexture>, game_config: Res<GameConfig>) {
+    let from = -game_config.world.size.y * game_config.world.step / 2.;
+    commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite {
+            custom_size: Some(Vec2::new(128., 64.)),
+            ..Default::default()
+        },
+        texture_atlas: game_texture.gameover.clone(),
+        transform: Transform::from_translation(Vec3::new(0., from, 5.)),
+        ..Default::default()
+    }).insert(GameOverBanner { from }).insert(StageEntity);
+    commands.insert_resource(StageTimer {
+        timer: Timer::from_seconds(game_config.stage.game_over, TimerMode::Once),
+        next: None,
+    });
+}
+
+//前一半时间升到画面中间, 时间到了进入结算
+fn game_over_update(time: Res<Time>, mut timer: ResMut<StageTimer>, mut query: Query<(&mut Transform, &GameOverBanner)>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    let timer = &mut timer.timer;
+    timer.tick(time.delta());
+    let rise = (timer.elapsed_secs() / (timer.duration().as_secs_f32() / 2.)).min(1.);
+    for (mut transform, banner) in &mut query {
+        transform.translation.y = banner.from * (1. - rise);
+    }
+    if timer.just_finished() {
+        playing_state.set(PlayingState::Tally);
+    }
+}
+
+fn curtain_cleanup(mut commands: Commands, query: Query<Entity, With<StageCurtain>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+//每关开始前清掉上一关留下的实体和道具效果
+fn stage_cleanup(mut commands: Commands, query: Query<Entity, With<StageEntity>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<EnemyFreeze>();
+    commands.remove_resource::<Fortify>();
+}
+
+//退出游戏时清掉所有游戏实体和这一局的道具效果, 再开始时从头生成, EnemySpawner留着下次开始时重置
+fn play_cleanup(mut commands: Commands, query: Query<Entity, Or<(With<StageEntity>, With<PlayEntity>)>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    commands.remove_resource::<EnemyFreeze>();
+    commands.remove_resource::<Fortify>();
+}
@@ -1,7 +1,14 @@
-use bevy::asset::io::memory::Value::Vec;
+use std::collections::{HashSet, VecDeque};
 use bevy::prelude::*;
-use crate::load::GameTexture;
-use crate::ui::menu::{full_screen_node_setup, MenuState};
+use bevy::ui::RelativeCursorPosition;
+use crate::{GameState, PlayingState};
+use crate::level::{GameLevel, LevelProblem};
+use crate::load::{BRICK_FULL, GameConfig, GameMap, GameTexture, LandType};
+use crate::score::Scores;
+use crate::stage::Stage;
+use crate::world::{GameMode, LandTiles, River, RiverFrame, leave_playing};
+use crate::ui::map_file::{map_file_buttons_setup, MapFileState};
+use crate::ui::menu::{full_screen_node_setup, MenuState, NORMAL_BUTTON};
 
 pub struct EditorPlugin;
 
@@ -10,8 +17,18 @@ impl Plugin for EditorPlugin {
         app.insert_resource(LandSelected {
             land: LandAction::TreeSelect,
             size: EditorAction::Double,
-        }).add_systems(OnEnter(MenuState::MapEdit), editor_setup)
-            .add_systems(Update, (print_mouse_position_system, editor_border_selected_update, editor_left_land_action).run_if(in_state(MenuState::MapEdit)));
+        }).insert_resource(ToolSelected {
+            tool: EditorTool::Brush,
+            mirror: false,
+            drag: None,
+            stroke: false,
+        }).init_resource::<EditorHistory>().init_resource::<EditorCursor>().init_resource::<EditorPreview>().init_resource::<MapCheck>()
+            .add_systems(OnEnter(MenuState::MapEdit), editor_setup)
+            .add_systems(OnExit(MenuState::MapEdit), editor_cleanup)
+            .add_systems(PostUpdate, playtest_escape.run_if(in_state(GameState::Playing)).run_if(not(in_state(PlayingState::Paused))).run_if(resource_exists::<Playtest>()))
+            .add_systems(Update, (editor_map_check, editor_problems_update).chain().run_if(in_state(MenuState::MapEdit)))
+            .add_systems(Update, (editor_tiles_update, editor_preview_update, editor_border_selected_update, editor_left_land_action, editor_tool_border_update, editor_tool_select, editor_playtest).run_if(in_state(MenuState::MapEdit)))
+            .add_systems(Update, (editor_cursor, editor_tool_action, editor_stroke_end, editor_undo_redo).chain().run_if(in_state(MenuState::MapEdit)).run_if(in_state(MapFileState::Closed)));
     }
 }
 
@@ -27,39 +44,171 @@ enum LandAction {
     IceSelect,
     IronSelect,
     BrickSelect,
-    //WaterSelect,
-    //GrassSelect,
+    WaterSelect,
+    GrassSelect,
     NoneSelect,
 }
 
+impl LandAction {
+    fn land(&self) -> LandType {
+        match self {
+            LandAction::TreeSelect => LandType::Tree,
+            LandAction::IceSelect => LandType::Ice,
+            LandAction::IronSelect => LandType::Iron,
+            LandAction::BrickSelect => LandType::Brick(BRICK_FULL),
+            LandAction::WaterSelect => LandType::Water,
+            LandAction::GrassSelect => LandType::Grass,
+            LandAction::NoneSelect => LandType::None,
+        }
+    }
+}
+
 #[derive(Resource)]
 struct LandSelected {
     land: LandAction,
     size: EditorAction,
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum EditorTool {
+    Brush,
+    Rect,
+    Line,
+    Fill,
+}
+
+//工具按钮, 对称是开关, 可以和任意工具一起用
+#[derive(Component, Debug, Clone, Copy, Eq, PartialEq)]
+enum ToolAction {
+    Tool(EditorTool),
+    Mirror,
+}
+
+//drag 是矩形和直线工具按下时的起点和要画的地形, stroke 表示这次按下鼠标时光标在画布上
+#[derive(Resource)]
+struct ToolSelected {
+    tool: EditorTool,
+    mirror: bool,
+    drag: Option<((i32, i32, i32), LandType)>,
+    stroke: bool,
+}
+
+//光标所在的笔刷范围(起始列, 起始行, 边长), 不在画布上时为None
+#[derive(Default, Resource)]
+struct EditorCursor(Option<(i32, i32, i32)>);
+
+//当前工具松开或点击后会画到的格子
+#[derive(Default, Resource)]
+struct EditorPreview {
+    cells: Vec<(i32, i32)>,
+}
+
+#[derive(Component)]
+struct PreviewNode;
+
+//编辑中地图的检查结果, 有问题时不能试玩也不能保存
+#[derive(Default, Resource)]
+pub struct MapCheck {
+    pub problems: Vec<LevelProblem>,
+}
+
+#[derive(Component)]
+struct ProblemPanel;
+
+#[derive(Component)]
+struct ProblemNode;
+
+//正在编辑的关卡, 离开编辑器后保留
+#[derive(Resource)]
+pub struct EditorLevel(pub GameLevel);
+
+//试玩编辑中的地图时保存原来的关卡, 回到编辑器时换回去
+#[derive(Resource)]
+pub struct Playtest(pub GameLevel);
+
+#[derive(Component)]
+struct PlaytestButton;
+
+#[derive(Component)]
+struct EditorRoot;
+
+//一格地形修改前后的样子
+struct TileChange {
+    col: i32,
+    row: i32,
+    before: LandType,
+    after: LandType,
+}
+
+//编辑历史, 每一项是一次完整的操作, 拖动画的一笔只算一项
+#[derive(Default, Resource)]
+pub struct EditorHistory {
+    undo: VecDeque<Vec<TileChange>>,
+    redo: Vec<Vec<TileChange>>,
+    stroke: Vec<TileChange>,
+}
+
+const EDITOR_HISTORY_LIMIT: usize = 100;
+
+impl EditorHistory {
+    //修改一格地形并记到当前这一笔里
+    fn paint(&mut self, map: &mut GameMap, col: i32, row: i32, land: &LandType) {
+        let Some(before) = map.get(col, row).cloned() else {
+            return;
+        };
+        if before != *land {
+            map.set(col, row, land.clone());
+            self.stroke.push(TileChange { col, row, before, after: land.clone() });
+        }
+    }
+
+    //结束当前这一笔, 超出上限时丢掉最早的记录
+    fn commit(&mut self) {
+        if self.stroke.is_empty() {
+            return;
+        }
+        self.undo.push_back(std::mem::take(&mut self.stroke));
+        if self.undo.len() > EDITOR_HISTORY_LIMIT {
+            self.undo.pop_front();
+        }
+        self.redo.clear();
+    }
+
+    pub fn clear(&mut self) {
+        *self = EditorHistory::default();
+    }
+}
+
+//画布上已经生成的地形图块, 关卡变化时只更新有变化的格子
+#[derive(Resource)]
+struct EditorTiles(LandTiles);
+
+//跟随光标的笔刷范围框
 #[derive(Component)]
 struct EditorAdd;
 
+#[derive(Component)]
+struct EditorCanvas;
+
 const SELECTED_BORDER: Color = Color::RED;
 const DEFAULT_BORDER: Color = Color::rgb(0.6, 0.6, 0.6);
+//画布就是背景图, 地图区域四周各有3像素的边框
+const EDITOR_CANVAS_SIZE: f32 = 630.;
+const EDITOR_CANVAS_BORDER: f32 = 3.;
 
-fn editor_setup(mut commands: Commands, textures: Res<GameTexture>) {
-    commands.spawn((ButtonBundle {
-        style: Style {
-            width: Val::Px(48.),
-            height: Val::Px(48.),
-            border: UiRect::all(Val::Px(2.)),
-            ..Default::default()
-        },
-        border_color: SELECTED_BORDER.into(),
-        transform: Transform::from_translation(Vec3::new(0.,0.,3.)),
-        ..Default::default()
-    }, EditorAdd));
-    commands.spawn(full_screen_node_setup()).with_children(|parent| {
+fn editor_setup(mut commands: Commands, textures: Res<GameTexture>, game_level: Res<GameLevel>, editor_level: Option<Res<EditorLevel>>) {
+    //第一次进入编辑器时从空地图开始, 基地和出生点沿用当前关卡
+    if editor_level.is_none() {
+        let mut level = game_level.clone();
+        level.map = GameMap::new(level.map.size, level.map.home);
+        commands.insert_resource(EditorLevel(level));
+    }
+    let size = game_level.map.size;
+    commands.insert_resource(EditorTiles(LandTiles::new(size)));
+    commands.spawn(full_screen_node_setup()).insert(EditorRoot).with_children(|parent| {
         parent.spawn(NodeBundle {
             style: Style {
-                width: Val::Px(730.),
+                width: Val::Px(890.),
                 height: Val::Px(630.),
                 display: Display::Flex,
                 flex_direction: FlexDirection::Row,
@@ -92,16 +241,53 @@ fn editor_setup(mut commands: Commands, textures: Res<GameTexture>) {
                 editor_single_land_setup(p, &textures.iron, LandAction::IronSelect);
                 editor_double_land_setup(p, &textures.brick, LandAction::BrickSelect);
                 editor_single_land_setup(p, &textures.brick, LandAction::BrickSelect);
+                editor_double_land_setup(p, &textures.river1, LandAction::WaterSelect);
+                editor_single_land_setup(p, &textures.river1, LandAction::WaterSelect);
+                editor_double_land_setup(p, &textures.tree, LandAction::GrassSelect);
+                editor_single_land_setup(p, &textures.tree, LandAction::GrassSelect);
+                editor_text_button_setup(p, ToolAction::Tool(EditorTool::Brush), "brush");
+                editor_text_button_setup(p, ToolAction::Tool(EditorTool::Rect), "rect");
+                editor_text_button_setup(p, ToolAction::Tool(EditorTool::Line), "line");
+                editor_text_button_setup(p, ToolAction::Tool(EditorTool::Fill), "fill");
+                editor_text_button_setup(p, ToolAction::Mirror, "mirror");
+                editor_text_button_setup(p, PlaytestButton, "test");
+                map_file_buttons_setup(p);
             });
             parent.spawn(AtlasImageBundle {
                 texture_atlas: textures.world.clone(),
                 ..Default::default()
+            }).insert((EditorCanvas, RelativeCursorPosition::default())).with_children(|p| {
+                p.spawn(NodeBundle {
+                    style: Style {
+                        position_type: PositionType::Absolute,
+                        border: UiRect::all(Val::Px(2.)),
+                        ..Default::default()
+                    },
+                    border_color: SELECTED_BORDER.into(),
+                    visibility: Visibility::Hidden,
+                    z_index: ZIndex::Local(1),
+                    ..Default::default()
+                }).insert(EditorAdd);
             });
+            parent.spawn(NodeBundle {
+                style: Style {
+                    border: UiRect::new(Val::Px(0.), Val::Px(3.), Val::Px(3.), Val::Px(3.)),
+                    display: Display::Flex,
+                    flex_direction: FlexDirection::Column,
+                    width: Val::Px(160.),
+                    height: Val::Percent(100.),
+                    padding: UiRect::all(Val::Px(4.)),
+                    ..Default::default()
+                },
+                border_color: DEFAULT_BORDER.into(),
+                ..Default::default()
+            }).insert(ProblemPanel);
         });
     });
 }
 
 fn editor_single_land_setup(parent: &mut ChildBuilder, textures: &Handle<TextureAtlas>, land_action: LandAction) {
+    let tint = land_action.land().tint();
     parent.spawn(ButtonBundle {
         style: Style {
             border: UiRect::all(Val::Px(2.)),
@@ -119,7 +305,13 @@ fn editor_single_land_setup(parent: &mut ChildBuilder, textures: &Handle<Texture
         ..Default::default()
     }).insert(EditorAction::Single).insert(land_action).with_children(|p| {
         p.spawn(AtlasImageBundle {
+            style: Style {
+                width: Val::Px(24.),
+                height: Val::Px(24.),
+                ..Default::default()
+            },
             texture_atlas: textures.clone(),
+            background_color: tint.into(),
             transform: Transform::from_scale(Vec3::ONE),
             ..Default::default()
         });
@@ -127,6 +319,7 @@ fn editor_single_land_setup(parent: &mut ChildBuilder, textures: &Handle<Texture
 }
 
 fn editor_double_land_setup(parent: &mut ChildBuilder, textures: &Handle<TextureAtlas>, land_action: LandAction) {
+    let tint = land_action.land().tint();
     parent.spawn(ButtonBundle {
         style: Style {
             border: UiRect::all(Val::Px(2.)),
@@ -144,28 +337,125 @@ fn editor_double_land_setup(parent: &mut ChildBuilder, textures: &Handle<Texture
         ..Default::default()
     }).insert(EditorAction::Double).insert(land_action).with_children(|p| {
         p.spawn(AtlasImageBundle {
+            style: Style {
+                width: Val::Px(24.),
+                height: Val::Px(24.),
+                ..Default::default()
+            },
             texture_atlas: textures.clone(),
+            background_color: tint.into(),
             transform: Transform::from_scale(Vec3::ONE),
             ..Default::default()
         });
         p.spawn(AtlasImageBundle {
+            style: Style {
+                width: Val::Px(24.),
+                height: Val::Px(24.),
+                ..Default::default()
+            },
             texture_atlas: textures.clone(),
+            background_color: tint.into(),
             transform: Transform::from_scale(Vec3::ONE),
             ..Default::default()
         });
         p.spawn(AtlasImageBundle {
+            style: Style {
+                width: Val::Px(24.),
+                height: Val::Px(24.),
+                ..Default::default()
+            },
             texture_atlas: textures.clone(),
+            background_color: tint.into(),
             transform: Transform::from_scale(Vec3::ONE),
             ..Default::default()
         });
         p.spawn(AtlasImageBundle {
+            style: Style {
+                width: Val::Px(24.),
+                height: Val::Px(24.),
+                ..Default::default()
+            },
             texture_atlas: textures.clone(),
+            background_color: tint.into(),
             transform: Transform::from_scale(Vec3::ONE),
             ..Default::default()
         });
     });
 }
 
+fn editor_text_button_setup(parent: &mut ChildBuilder, action: impl Component, text: &str) {
+    parent.spawn(ButtonBundle {
+        style: Style {
+            border: UiRect::all(Val::Px(2.)),
+            width: Val::Px(43.),
+            height: Val::Px(24.),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(1.)),
+            ..Default::default()
+        },
+        border_color: DEFAULT_BORDER.into(),
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }).insert(action).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 12.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..Default::default()
+            }));
+    });
+}
+
+fn editor_tool_select(query: Query<(&Interaction, &ToolAction), (Changed<Interaction>, With<Button>)>, mut tool_selected: ResMut<ToolSelected>) {
+    for (interaction, tool_action) in &query {
+        if *interaction == Interaction::Pressed {
+            match tool_action {
+                ToolAction::Tool(tool) => tool_selected.tool = *tool,
+                ToolAction::Mirror => tool_selected.mirror = !tool_selected.mirror,
+            }
+            tool_selected.drag = None;
+        }
+    }
+}
+
+fn editor_tool_border_update(mut query: Query<(&mut BorderColor, &ToolAction), With<Button>>, tool_selected: Res<ToolSelected>) {
+    for (mut border, tool_action) in &mut query {
+        let selected = match tool_action {
+            ToolAction::Tool(tool) => tool_selected.tool == *tool,
+            ToolAction::Mirror => tool_selected.mirror,
+        };
+        *border = if selected { SELECTED_BORDER.into() } else { DEFAULT_BORDER.into() };
+    }
+}
+
+fn editor_cleanup(mut commands: Commands, root: Query<Entity, With<EditorRoot>>) {
+    for entity in &root {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+//不保存直接用编辑中的地图开始游戏, 编辑器的资源都保留着, 地图检查有问题时不能试玩
+fn editor_playtest(mut commands: Commands, query: Query<&Interaction, (Changed<Interaction>, With<PlaytestButton>)>, map_check: Res<MapCheck>, editor_level: Res<EditorLevel>, game_level: Res<GameLevel>, mut game_state: ResMut<NextState<GameState>>) {
+    if query.iter().any(|interaction| *interaction == Interaction::Pressed) && map_check.problems.is_empty() {
+        commands.insert_resource(Playtest(game_level.clone()));
+        commands.insert_resource(editor_level.0.clone());
+        commands.insert_resource(GameMode::Single);
+        commands.insert_resource(Scores::default());
+        commands.insert_resource(Stage::default());
+        game_state.set(GameState::Spawn);
+    }
+}
+
+//试玩时按Esc回到菜单, 由菜单转回编辑器, 暂停时Esc留给暂停菜单
+//放在PostUpdate里, 同一帧过关或者游戏结束改的PlayingState会被Disabled盖掉
+fn playtest_escape(keyboard_input: Res<Input<KeyCode>>, mut game_state: ResMut<NextState<GameState>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if keyboard_input.just_pressed(KeyCode::Escape) {
+        leave_playing(&mut game_state, &mut playing_state);
+    }
+}
+
 fn editor_left_land_action(mut query: Query<(&Interaction, &LandAction, &EditorAction), (Changed<Interaction>, With<Button>)>, mut land_selected: ResMut<LandSelected>) {
     for (interaction, land, editor) in &mut query {
         if *interaction == Interaction::Pressed {
@@ -183,22 +473,299 @@ fn editor_border_selected_update(mut query: Query<(&mut BorderColor, &LandAction
     }
 }
 
-fn print_mouse_position_system(
-    mut cursor_moved_events: EventReader<CursorMoved>,
-    windows: Query<&Window>,
-    mut editor_add: Query<&mut Transform, With<EditorAdd>>,
-) {
-    let window = windows.single();
-    for event in cursor_moved_events.read() {
-        let pos = get_window_position(&event.position, window, &Vec2::new(100., 0.));
-        if pos.x < 312. && pos.x > -312. && pos.y < 312. && pos.y > -312. {
-            let _ = editor_add.single_mut().with_translation(Vec3::new(pos.x, pos.y, 2.));
-            /*(*editor_add.single_mut()).translation = Vec3::new(pos.x, pos.y, 2.);*/
-            println!("123")
+//笔刷覆盖的格子(起始列, 起始行, 边长), 单格取光标所在的格子, 双格取离光标最近的格点周围2x2格子
+fn brush_cells(cursor: Vec2, size: &EditorAction, map: &GameMap, step: f32) -> Option<(i32, i32, i32)> {
+    let pos = cursor * EDITOR_CANVAS_SIZE - Vec2::splat(EDITOR_CANVAS_BORDER);
+    let (cols, rows) = (map.size.x as i32, map.size.y as i32);
+    let (col, row) = ((pos.x / step).floor() as i32, (pos.y / step).floor() as i32);
+    if col < 0 || row < 0 || col >= cols || row >= rows {
+        return None;
+    }
+    match size {
+        EditorAction::Single => Some((col, row, 1)),
+        EditorAction::Double => {
+            let col = ((pos.x / step).round() as i32 - 1).clamp(0, cols - 2);
+            let row = ((pos.y / step).round() as i32 - 1).clamp(0, rows - 2);
+            Some((col, row, 2))
+        }
+    }
+}
+
+//光标对齐到格子显示笔刷范围
+fn editor_cursor(game_config: Res<GameConfig>, land_selected: Res<LandSelected>, editor_level: Res<EditorLevel>, mut editor_cursor: ResMut<EditorCursor>, canvas: Query<&RelativeCursorPosition, With<EditorCanvas>>, mut editor_add: Query<(&mut Style, &mut Visibility), With<EditorAdd>>) {
+    let (Ok(cursor), Ok((mut style, mut visibility))) = (canvas.get_single(), editor_add.get_single_mut()) else {
+        return;
+    };
+    let step = game_config.world.step;
+    let brush = cursor.normalized.filter(|_| cursor.mouse_over()).and_then(|cursor| brush_cells(cursor, &land_selected.size, &editor_level.0.map, step));
+    editor_cursor.0 = brush;
+    let Some((col, row, width)) = brush else {
+        *visibility = Visibility::Hidden;
+        return;
+    };
+    *visibility = Visibility::Inherited;
+    style.left = Val::Px(EDITOR_CANVAS_BORDER + col as f32 * step);
+    style.top = Val::Px(EDITOR_CANVAS_BORDER + row as f32 * step);
+    style.width = Val::Px(width as f32 * step);
+    style.height = Val::Px(width as f32 * step);
+}
+
+//笔刷盖住的格子
+fn stamp_cells((col, row, width): (i32, i32, i32)) -> Vec<(i32, i32)> {
+    (row..row + width).flat_map(|row| (col..col + width).map(move |col| (col, row))).collect()
+}
+
+//两个笔刷位置围成的矩形
+fn rect_cells(start: (i32, i32, i32), end: (i32, i32, i32)) -> Vec<(i32, i32)> {
+    let (min_col, max_col) = (start.0.min(end.0), start.0.max(end.0) + end.2 - 1);
+    let (min_row, max_row) = (start.1.min(end.1), start.1.max(end.1) + end.2 - 1);
+    (min_row..=max_row).flat_map(|row| (min_col..=max_col).map(move |col| (col, row))).collect()
+}
+
+//两个笔刷位置之间的直线, 每一步盖一次笔刷
+fn line_cells(start: (i32, i32, i32), end: (i32, i32, i32)) -> Vec<(i32, i32)> {
+    let (dx, dy) = (end.0 - start.0, end.1 - start.1);
+    let steps = dx.abs().max(dy.abs()).max(1);
+    (0..=steps).flat_map(|i| {
+        let t = i as f32 / steps as f32;
+        stamp_cells(((start.0 as f32 + dx as f32 * t).round() as i32, (start.1 as f32 + dy as f32 * t).round() as i32, end.2))
+    }).collect()
+}
+
+//和起点地形相同并且上下左右连在一起的格子
+fn fill_cells(map: &GameMap, col: i32, row: i32) -> Vec<(i32, i32)> {
+    let Some(target) = map.get(col, row) else {
+        return Vec::new();
+    };
+    let mut visited = HashSet::from([(col, row)]);
+    let mut queue = VecDeque::from([(col, row)]);
+    while let Some((col, row)) = queue.pop_front() {
+        for next in [(col - 1, row), (col + 1, row), (col, row - 1), (col, row + 1)] {
+            if map.get(next.0, next.1) == Some(target) && visited.insert(next) {
+                queue.push_back(next);
+            }
         }
     }
+    visited.into_iter().collect()
 }
 
-fn get_window_position(pos: &Vec2, window: &Window, offset: &Vec2) -> Vec2 {
-    Vec2::new(pos.x - (window.width() + offset.x) / 2., pos.y - (window.height() + offset.y) / 2.)
-}
\ No newline at end of file
+//左键画上选中的地形, 右键擦除. 笔刷按住直接画, 矩形和直线拖动时预览松开后画, 填充预览光标所在的整片区域点击后画, 对称模式同时画到竖直中线的另一边
+fn editor_tool_action(mouse_input: Res<Input<MouseButton>>, editor_cursor: Res<EditorCursor>, land_selected: Res<LandSelected>, mut tool_selected: ResMut<ToolSelected>, mut editor_level: ResMut<EditorLevel>, mut history: ResMut<EditorHistory>, mut preview: ResMut<EditorPreview>) {
+    //对话框的按钮盖在画布上, 点完按钮对话框关掉后鼠标还按着, 只有在画布上按下的才算
+    if mouse_input.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+        tool_selected.stroke = editor_cursor.0.is_some();
+    } else if !mouse_input.any_pressed([MouseButton::Left, MouseButton::Right]) {
+        tool_selected.stroke = false;
+    }
+    let land = if !tool_selected.stroke {
+        None
+    } else if mouse_input.pressed(MouseButton::Left) {
+        Some(land_selected.land.land())
+    } else if mouse_input.pressed(MouseButton::Right) {
+        Some(LandType::None)
+    } else {
+        None
+    };
+    let mut cells = Vec::new();
+    let mut paint = None;
+    match (tool_selected.tool, editor_cursor.0) {
+        (_, None) => {
+            if land.is_none() {
+                tool_selected.drag = None;
+            }
+        }
+        (EditorTool::Brush, Some(cursor)) => {
+            cells = stamp_cells(cursor);
+            paint = land;
+        }
+        (EditorTool::Rect | EditorTool::Line, Some(cursor)) => match tool_selected.drag.clone() {
+            None => {
+                cells = stamp_cells(cursor);
+                if let Some(land) = land {
+                    tool_selected.drag = Some((cursor, land));
+                }
+            }
+            Some((start, drag_land)) => {
+                cells = if tool_selected.tool == EditorTool::Rect { rect_cells(start, cursor) } else { line_cells(start, cursor) };
+                if land.is_none() {
+                    tool_selected.drag = None;
+                    paint = Some(drag_land);
+                }
+            }
+        },
+        (EditorTool::Fill, Some((col, row, _))) => {
+            cells = fill_cells(&editor_level.0.map, col, row);
+            if mouse_input.any_just_pressed([MouseButton::Left, MouseButton::Right]) {
+                paint = land;
+            }
+        }
+    }
+    let map = &editor_level.0.map;
+    if tool_selected.mirror {
+        let cols = map.size.x as i32;
+        let mirrored: Vec<_> = cells.iter().map(|&(col, row)| (cols - 1 - col, row)).collect();
+        cells.extend(mirrored);
+    }
+    cells.retain(|&(col, row)| map.get(col, row).is_some());
+    cells.sort();
+    cells.dedup();
+    if let Some(land) = paint {
+        for &(col, row) in cells.iter() {
+            if editor_level.0.map.get(col, row) != Some(&land) {
+                history.paint(&mut editor_level.0.map, col, row, &land);
+            }
+        }
+    }
+    if preview.cells != cells {
+        preview.cells = cells;
+    }
+}
+
+//预览的格子变化后重新生成半透明的预览图块
+fn editor_preview_update(mut commands: Commands, game_config: Res<GameConfig>, preview: Res<EditorPreview>, canvas: Query<Entity, With<EditorCanvas>>, nodes: Query<Entity, With<PreviewNode>>) {
+    let Ok(canvas) = canvas.get_single() else {
+        return;
+    };
+    if !preview.is_changed() {
+        return;
+    }
+    for entity in &nodes {
+        commands.entity(entity).despawn();
+    }
+    let step = game_config.world.step;
+    for &(col, row) in preview.cells.iter() {
+        let node = commands.spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(EDITOR_CANVAS_BORDER + col as f32 * step),
+                top: Val::Px(EDITOR_CANVAS_BORDER + row as f32 * step),
+                width: Val::Px(step),
+                height: Val::Px(step),
+                ..Default::default()
+            },
+            background_color: Color::rgba(1., 1., 1., 0.3).into(),
+            z_index: ZIndex::Local(1),
+            ..Default::default()
+        }).insert(PreviewNode).id();
+        commands.entity(canvas).add_child(node);
+    }
+}
+
+//松开鼠标时这一笔画完, 记成一项历史
+fn editor_stroke_end(mouse_input: Res<Input<MouseButton>>, mut history: ResMut<EditorHistory>) {
+    if !mouse_input.pressed(MouseButton::Left) && !mouse_input.pressed(MouseButton::Right) && !history.stroke.is_empty() {
+        history.commit();
+    }
+}
+
+//Ctrl+Z 撤销, Ctrl+Y 重做, 正在画的时候不处理
+fn editor_undo_redo(keyboard_input: Res<Input<KeyCode>>, mut history: ResMut<EditorHistory>, mut editor_level: ResMut<EditorLevel>) {
+    if !keyboard_input.any_pressed([KeyCode::ControlLeft, KeyCode::ControlRight]) || !history.stroke.is_empty() {
+        return;
+    }
+    if keyboard_input.just_pressed(KeyCode::Z) {
+        if let Some(changes) = history.undo.pop_back() {
+            for change in changes.iter().rev() {
+                editor_level.0.map.set(change.col, change.row, change.before.clone());
+            }
+            history.redo.push(changes);
+        }
+    } else if keyboard_input.just_pressed(KeyCode::Y) {
+        if let Some(changes) = history.redo.pop() {
+            for change in changes.iter() {
+                editor_level.0.map.set(change.col, change.row, change.after.clone());
+            }
+            history.undo.push_back(changes);
+        }
+    }
+}
+
+//画布上一格地形的图块, 和游戏地图一样除了铁块都按四分之一格分别生成
+fn spawn_editor_land(commands: &mut Commands, textures: &GameTexture, canvas: Entity, (col, row): (i32, i32), land: &LandType, step: f32, river_frame: usize) -> Vec<Entity> {
+    let (left, top) = (EDITOR_CANVAS_BORDER + col as f32 * step, EDITOR_CANVAS_BORDER + row as f32 * step);
+    textures.land(land, river_frame).into_iter().map(|(texture_atlas, index, quarter)| {
+        let (position, size) = match quarter {
+            Some(quarter) => (Vec2::new(left + (quarter % 2) as f32 * step / 2., top + (quarter / 2) as f32 * step / 2.), step / 2.),
+            None => (Vec2::new(left, top), step),
+        };
+        let mut node = commands.spawn(AtlasImageBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                left: Val::Px(position.x),
+                top: Val::Px(position.y),
+                width: Val::Px(size),
+                height: Val::Px(size),
+                ..Default::default()
+            },
+            texture_atlas,
+            texture_atlas_image: UiTextureAtlasImage { index, ..Default::default() },
+            background_color: land.tint().into(),
+            ..Default::default()
+        });
+        if *land == LandType::Water {
+            node.insert(River);
+        }
+        let node = node.id();
+        commands.entity(canvas).add_child(node);
+        node
+    }).collect()
+}
+
+//关卡变化后和画布上的图块对比, 只重新生成变化的格子
+fn editor_tiles_update(mut commands: Commands, textures: Res<GameTexture>, game_config: Res<GameConfig>, editor_level: Res<EditorLevel>, river_frame: Res<RiverFrame>, mut tiles: ResMut<EditorTiles>, canvas: Query<Entity, With<EditorCanvas>>) {
+    let Ok(canvas) = canvas.get_single() else {
+        return;
+    };
+    if !editor_level.is_changed() && !tiles.is_added() {
+        return;
+    }
+    let step = game_config.world.step;
+    tiles.0.update(&mut commands, &editor_level.0.map.data, |commands, cell, land| spawn_editor_land(commands, &textures, canvas, cell, land, step, river_frame.frame));
+}
+
+fn editor_map_check(editor_level: Res<EditorLevel>, mut map_check: ResMut<MapCheck>) {
+    if editor_level.is_changed() {
+        map_check.problems = editor_level.0.problems();
+    }
+}
+
+//右侧列出地图的问题, 相关的格子在画布上标红
+fn editor_problems_update(mut commands: Commands, game_config: Res<GameConfig>, map_check: Res<MapCheck>, panel: Query<Entity, Added<ProblemPanel>>, panels: Query<Entity, With<ProblemPanel>>, canvas: Query<Entity, With<EditorCanvas>>, nodes: Query<Entity, With<ProblemNode>>) {
+    if !map_check.is_changed() && panel.is_empty() {
+        return;
+    }
+    let (Ok(panel), Ok(canvas)) = (panels.get_single(), canvas.get_single()) else {
+        return;
+    };
+    for entity in &nodes {
+        commands.entity(entity).despawn_recursive();
+    }
+    let style = TextStyle {
+        font_size: 14.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+        ..Default::default()
+    };
+    let title = if map_check.problems.is_empty() { "map ok" } else { "fix before testing:" };
+    let mut lines = vec![commands.spawn(TextBundle::from_section(title, style.clone())).insert(ProblemNode).id()];
+    let step = game_config.world.step;
+    for problem in map_check.problems.iter() {
+        lines.push(commands.spawn(TextBundle::from_section(format!("- {}", problem.message), TextStyle { color: Color::rgb(1., 0.4, 0.4), ..style.clone() })).insert(ProblemNode).id());
+        for &(col, row) in problem.cells.iter() {
+            let node = commands.spawn(NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    left: Val::Px(EDITOR_CANVAS_BORDER + col as f32 * step),
+                    top: Val::Px(EDITOR_CANVAS_BORDER + row as f32 * step),
+                    width: Val::Px(step),
+                    height: Val::Px(step),
+                    ..Default::default()
+                },
+                background_color: Color::rgba(1., 0., 0., 0.4).into(),
+                z_index: ZIndex::Local(1),
+                ..Default::default()
+            }).insert(ProblemNode).id();
+            commands.entity(canvas).add_child(node);
+        }
+    }
+    commands.entity(panel).push_children(&lines);
+}
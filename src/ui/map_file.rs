@@ -0,0 +1,279 @@
+use std::fs;
+use std::path::PathBuf;
+use bevy::prelude::*;
+use crate::level::GameLevel;
+use crate::ui::editor::{EditorHistory, EditorLevel, MapCheck};
+use crate::ui::menu::{full_screen_node_setup, MenuState, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON};
+
+pub struct MapFilePlugin;
+
+impl Plugin for MapFilePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_state::<MapFileState>().init_resource::<MapFile>()
+            .add_systems(OnEnter(MapFileState::SaveAs), save_as_setup)
+            .add_systems(OnEnter(MapFileState::Overwrite), overwrite_setup)
+            .add_systems(OnEnter(MapFileState::Open), open_setup)
+            .add_systems(OnExit(MapFileState::SaveAs), dialog_cleanup)
+            .add_systems(OnExit(MapFileState::Overwrite), dialog_cleanup)
+            .add_systems(OnExit(MapFileState::Open), dialog_cleanup)
+            .add_systems(OnExit(MenuState::MapEdit), map_file_cleanup)
+            .add_systems(Update, (file_button_system, file_status_update).run_if(in_state(MenuState::MapEdit)))
+            .add_systems(Update, file_name_input.run_if(in_state(MapFileState::SaveAs)));
+    }
+}
+
+//用户地图放在运行目录下, 和游戏自带的关卡分开
+const USER_MAPS_DIR: &str = "maps";
+const LEVEL_SUFFIX: &str = ".level.json";
+
+//编辑器里打开的对话框, 打开时不能在画布上画
+#[derive(Copy, Clone, Default, Eq, PartialEq, Hash, Debug, States)]
+pub enum MapFileState {
+    #[default]
+    Closed,
+    SaveAs,
+    Overwrite,
+    Open,
+}
+
+//当前编辑的文件名(不带后缀), 另存为时输入的文件名和最近一次操作的结果
+#[derive(Default, Resource)]
+struct MapFile {
+    name: Option<String>,
+    input: String,
+    status: String,
+}
+
+#[derive(Component, Clone)]
+enum FileAction {
+    Save,
+    SaveAs,
+    Open,
+    Confirm,
+    Cancel,
+    OpenFile(String),
+}
+
+#[derive(Component)]
+struct FileDialog;
+
+#[derive(Component)]
+struct FileStatus;
+
+#[derive(Component)]
+struct FileNameInput;
+
+fn map_path(name: &str) -> PathBuf {
+    PathBuf::from(USER_MAPS_DIR).join(format!("{}{}", name, LEVEL_SUFFIX))
+}
+
+fn map_names() -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(USER_MAPS_DIR).map(|dir| {
+        dir.filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().and_then(|name| name.strip_suffix(LEVEL_SUFFIX)).map(String::from))
+            .collect()
+    }).unwrap_or_default();
+    names.sort();
+    names
+}
+
+fn write_level(level: &GameLevel, name: &str) -> Result<(), String> {
+    fs::create_dir_all(USER_MAPS_DIR).map_err(|err| err.to_string())?;
+    let json = level.to_json().map_err(|err| err.to_string())?;
+    fs::write(map_path(name), json).map_err(|err| err.to_string())
+}
+
+fn read_level(name: &str) -> Result<GameLevel, String> {
+    let json = fs::read_to_string(map_path(name)).map_err(|err| err.to_string())?;
+    GameLevel::from_json(&json).map_err(|err| err.to_string())
+}
+
+fn save(map_file: &mut MapFile, level: &GameLevel, name: String) {
+    map_file.status = match write_level(level, &name) {
+        Ok(()) => format!("saved {}", name),
+        Err(err) => format!("save failed: {}", err),
+    };
+    map_file.name = Some(name);
+}
+
+//编辑器左侧的文件按钮和状态文字
+pub fn map_file_buttons_setup(parent: &mut ChildBuilder) {
+    file_button_setup(parent, FileAction::Save, "save");
+    file_button_setup(parent, FileAction::SaveAs, "save as");
+    file_button_setup(parent, FileAction::Open, "open");
+    parent.spawn(TextBundle::from_section("", TextStyle {
+        font_size: 14.0,
+        color: Color::rgb(0.9, 0.9, 0.9),
+        ..Default::default()
+    }).with_style(Style {
+        max_width: Val::Px(90.),
+        margin: UiRect::all(Val::Px(2.)),
+        ..Default::default()
+    })).insert(FileStatus);
+}
+
+fn file_button_setup(parent: &mut ChildBuilder, action: FileAction, text: &str) {
+    parent.spawn(ButtonBundle {
+        style: Style {
+            width: Val::Px(90.0),
+            height: Val::Px(30.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(2.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }).insert(action).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 16.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..Default::default()
+            }));
+    });
+}
+
+//对话框盖在整个编辑器上面
+fn dialog_setup(commands: &mut Commands, title: &str, content: impl FnOnce(&mut ChildBuilder)) {
+    let mut root = full_screen_node_setup();
+    root.style.position_type = PositionType::Absolute;
+    root.background_color = Color::rgba(0., 0., 0., 0.8).into();
+    root.z_index = ZIndex::Global(10);
+    commands.spawn(root).insert(FileDialog).with_children(|parent| {
+        parent.spawn(TextBundle::from_section(title, TextStyle {
+            font_size: 20.0,
+            color: Color::rgb(0.9, 0.9, 0.9),
+            ..Default::default()
+        }));
+        content(parent);
+    });
+}
+
+fn save_as_setup(mut commands: Commands, map_file: Res<MapFile>) {
+    dialog_setup(&mut commands, "save as", |parent| {
+        parent.spawn(TextBundle::from_section(format!("{}{}", map_file.input, LEVEL_SUFFIX), TextStyle {
+            font_size: 20.0,
+            color: Color::YELLOW,
+            ..Default::default()
+        }).with_style(Style {
+            margin: UiRect::all(Val::Px(10.)),
+            ..Default::default()
+        })).insert(FileNameInput);
+        file_button_setup(parent, FileAction::Confirm, "save");
+        file_button_setup(parent, FileAction::Cancel, "cancel");
+    });
+}
+
+fn overwrite_setup(mut commands: Commands, map_file: Res<MapFile>) {
+    dialog_setup(&mut commands, &format!("overwrite {}{}?", map_file.input, LEVEL_SUFFIX), |parent| {
+        file_button_setup(parent, FileAction::Confirm, "yes");
+        file_button_setup(parent, FileAction::Cancel, "no");
+    });
+}
+
+fn open_setup(mut commands: Commands) {
+    let names = map_names();
+    let title = if names.is_empty() { "no saved maps" } else { "open" };
+    dialog_setup(&mut commands, title, |parent| {
+        for name in names {
+            file_button_setup(parent, FileAction::OpenFile(name.clone()), &name);
+        }
+        file_button_setup(parent, FileAction::Cancel, "cancel");
+    });
+}
+
+fn dialog_cleanup(mut commands: Commands, dialog: Query<Entity, With<FileDialog>>) {
+    for entity in &dialog {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+fn map_file_cleanup(mut file_state: ResMut<NextState<MapFileState>>) {
+    file_state.set(MapFileState::Closed);
+}
+
+//另存为时文件名只接受字母数字和下划线横线
+fn file_name_input(mut events: EventReader<ReceivedCharacter>, mut map_file: ResMut<MapFile>, mut query: Query<&mut Text, With<FileNameInput>>) {
+    for event in events.read() {
+        match event.char {
+            '\u{8}' => {
+                map_file.input.pop();
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '-' => map_file.input.push(c),
+            _ => {}
+        }
+    }
+    if map_file.is_changed() {
+        for mut text in &mut query {
+            text.sections[0].value = format!("{}{}", map_file.input, LEVEL_SUFFIX);
+        }
+    }
+}
+
+//保存时没有文件名就转到另存为, 另存为覆盖已有的其他文件前先确认, 打开的地图尺寸要和当前一致, 打开后清空编辑历史
+fn file_button_system(mut query: Query<(&Interaction, &mut BackgroundColor, &FileAction), (Changed<Interaction>, With<Button>)>, file_state: Res<State<MapFileState>>, mut next_file_state: ResMut<NextState<MapFileState>>, mut map_file: ResMut<MapFile>, mut editor_level: ResMut<EditorLevel>, mut history: ResMut<EditorHistory>, map_check: Res<MapCheck>) {
+    for (interaction, mut color, action) in &mut query {
+        *color = match *interaction {
+            Interaction::Hovered => HOVERED_BUTTON.into(),
+            Interaction::Pressed => PRESSED_BUTTON.into(),
+            _ => NORMAL_BUTTON.into()
+        };
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            //地图有问题时存下来的关卡没法玩, 先让玩家改好
+            FileAction::Save | FileAction::SaveAs | FileAction::Confirm if !map_check.problems.is_empty() => {
+                map_file.status = "save failed: fix the map problems first".to_string();
+                next_file_state.set(MapFileState::Closed);
+            }
+            FileAction::Save => match map_file.name.clone() {
+                Some(name) => save(&mut map_file, &editor_level.0, name),
+                None => next_file_state.set(MapFileState::SaveAs),
+            },
+            FileAction::SaveAs => {
+                map_file.input = map_file.name.clone().unwrap_or_default();
+                next_file_state.set(MapFileState::SaveAs);
+            }
+            FileAction::Open => next_file_state.set(MapFileState::Open),
+            FileAction::Confirm => {
+                let name = map_file.input.clone();
+                if name.is_empty() {
+                    continue;
+                }
+                if *file_state.get() == MapFileState::SaveAs && map_file.name.as_ref() != Some(&name) && map_path(&name).exists() {
+                    next_file_state.set(MapFileState::Overwrite);
+                } else {
+                    save(&mut map_file, &editor_level.0, name);
+                    next_file_state.set(MapFileState::Closed);
+                }
+            }
+            FileAction::Cancel => next_file_state.set(MapFileState::Closed),
+            FileAction::OpenFile(name) => {
+                map_file.status = match read_level(name) {
+                    Ok(level) if level.map.size != editor_level.0.map.size => {
+                        format!("open failed: map size must be {}x{}", editor_level.0.map.size.x, editor_level.0.map.size.y)
+                    }
+                    Ok(level) => {
+                        editor_level.0 = level;
+                        history.clear();
+                        map_file.name = Some(name.clone());
+                        format!("opened {}", name)
+                    }
+                    Err(err) => format!("open failed: {}", err),
+                };
+                next_file_state.set(MapFileState::Closed);
+            }
+        }
+    }
+}
+
+fn file_status_update(map_file: Res<MapFile>, mut query: Query<&mut Text, With<FileStatus>>) {
+    if map_file.is_changed() {
+        for mut text in &mut query {
+            text.sections[0].value = map_file.status.clone();
+        }
+    }
+}
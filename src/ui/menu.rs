@@ -1,14 +1,20 @@
 use bevy::prelude::*;
 use lazy_static::lazy_static;
 use crate::GameState;
+use crate::level::Campaign;
 use crate::load::{GameConfig, GameTexture};
+use crate::score::Scores;
+use crate::stage::Stage;
+use crate::ui::editor::Playtest;
 use crate::utils::{Vec2Ext, Vec3Ext};
+use crate::world::GameMode;
 
 pub struct MenuPlugin;
 
 impl Plugin for MenuPlugin {
     fn build(&self, app: &mut App) {
         app.add_state::<MenuState>().add_systems(OnEnter(GameState::Menu), menu_setup)
+            .add_systems(OnExit(GameState::Menu), menu_exit)
             .add_systems(OnEnter(MenuState::Main), main_menu_setup)
             .add_systems(Update, button_system.run_if(in_state(MenuState::Main)))
             .add_systems(OnExit(MenuState::Main), menu_cleanup);
@@ -33,10 +39,10 @@ enum MenuAction {
     MapEdit,
 }
 
-const NORMAL_BUTTON: Color = Color::rgb(0.2, 0.2, 0.2);
-const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
+pub const NORMAL_BUTTON: Color = Color::rgb(0.2, 0.2, 0.2);
+pub const HOVERED_BUTTON: Color = Color::rgb(0.25, 0.25, 0.25);
 
-const PRESSED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
+pub const PRESSED_BUTTON: Color = Color::rgb(0.3, 0.3, 0.3);
 
 pub fn full_screen_node_setup() -> NodeBundle {
     NodeBundle {
@@ -54,8 +60,20 @@ pub fn full_screen_node_setup() -> NodeBundle {
     }
 }
 
-fn menu_setup(mut menu_state: ResMut<NextState<MenuState>>) {
-    menu_state.set(MenuState::Main);
+//试玩结束时换回原来的关卡并回到编辑器
+fn menu_setup(mut commands: Commands, playtest: Option<Res<Playtest>>, mut menu_state: ResMut<NextState<MenuState>>) {
+    if let Some(playtest) = playtest {
+        commands.insert_resource(playtest.0.clone());
+        commands.remove_resource::<Playtest>();
+        menu_state.set(MenuState::MapEdit);
+    } else {
+        menu_state.set(MenuState::Main);
+    }
+}
+
+//开始游戏或试玩时关掉菜单和编辑器, 回到菜单时重新生成
+fn menu_exit(mut menu_state: ResMut<NextState<MenuState>>) {
+    menu_state.set(MenuState::Disabled);
 }
 
 fn main_menu_setup(mut commands: Commands, game_config: Res<GameConfig>, game_texture: Res<GameTexture>, assets: Res<Assets<TextureAtlas>>) {
@@ -99,7 +117,8 @@ fn button_setup(parent: &mut ChildBuilder, action: MenuAction, text: &str) {
     });
 }
 
-fn button_system(mut query: Query<(&Interaction, &mut BackgroundColor, &MenuAction), (Changed<Interaction>, With<Button>)>, mut game_state: ResMut<NextState<GameState>>, mut map_state: ResMut<NextState<MenuState>>) {
+//开始游戏时记下单人还是双人, 从战役的第一关打起
+fn button_system(mut commands: Commands, mut query: Query<(&Interaction, &mut BackgroundColor, &MenuAction), (Changed<Interaction>, With<Button>)>, campaign: Res<Campaign>, mut game_state: ResMut<NextState<GameState>>, mut map_state: ResMut<NextState<MenuState>>) {
     for (interaction, mut color, action) in &mut query {
         *color = match *interaction {
             Interaction::Hovered => HOVERED_BUTTON.into(),
@@ -109,10 +128,12 @@ fn button_system(mut query: Query<(&Interaction, &mut BackgroundColor, &MenuActi
         if *interaction == Interaction::Pressed {
             match action {
                 MenuAction::Player1 => {
+                    start_campaign(&mut commands, &campaign, GameMode::Single);
                     game_state.set(GameState::Spawn);
                     println!("player1");
                 }
                 MenuAction::Player2 => {
+                    start_campaign(&mut commands, &campaign, GameMode::Double);
                     game_state.set(GameState::Spawn);
                     println!("player2");
                 }
@@ -126,6 +147,13 @@ fn button_system(mut query: Query<(&Interaction, &mut BackgroundColor, &MenuActi
     }
 }
 
+fn start_campaign(commands: &mut Commands, campaign: &Campaign, game_mode: GameMode) {
+    commands.insert_resource(game_mode);
+    commands.insert_resource(Scores::default());
+    commands.insert_resource(Stage::default());
+    commands.insert_resource(campaign.levels[0].clone());
+}
+
 fn menu_cleanup(mut commands: Commands, menu: Query<Entity, With<MenuAction>>) {
     for entity in menu.iter() {
         commands.entity(entity).despawn_recursive();
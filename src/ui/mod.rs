@@ -1,2 +1,4 @@
 pub mod menu;
-pub mod editor;
\ No newline at end of file
+pub mod editor;
+pub mod map_file;
+pub mod pause;
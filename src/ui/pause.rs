@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use crate::{GameState, PlayingState};
+use crate::ui::editor::Playtest;
+use crate::ui::menu::{full_screen_node_setup, HOVERED_BUTTON, NORMAL_BUTTON, PRESSED_BUTTON};
+use crate::world::{GAME_PLAYER1_KEY_CODE, GAME_PLAYER2_KEY_CODE, FireKeys, leave_playing};
+
+pub struct PausePlugin;
+
+impl Plugin for PausePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<FireKeyRebind>()
+            .add_systems(OnEnter(PlayingState::Paused), pause_setup)
+            .add_systems(OnExit(PlayingState::Paused), pause_cleanup)
+            .add_systems(Update, pause_input.run_if(in_state(PlayingState::Running)))
+            .add_systems(Update, ((resume_input, fire_key_rebind).chain(), pause_button_system, fire_key_label_update).run_if(in_state(PlayingState::Paused)));
+    }
+}
+
+#[derive(Component, Clone)]
+enum PauseAction {
+    Resume,
+    Restart,
+    Settings,
+    FireKey(bool),
+    Back,
+    Quit,
+}
+
+#[derive(Component)]
+struct PauseOverlay;
+
+#[derive(Component)]
+struct PauseMain;
+
+#[derive(Component)]
+struct PauseSettings;
+
+//开炮按键按钮上的文字, true 是1P
+#[derive(Component)]
+struct FireKeyLabel(bool);
+
+//正在等待按键的开炮按键, true 是1P
+#[derive(Default, Resource)]
+struct FireKeyRebind(Option<bool>);
+
+//试玩时Esc用来回到编辑器, 只能用P暂停
+fn pause_key_pressed(keyboard_input: &Input<KeyCode>, playtest: bool) -> bool {
+    keyboard_input.just_pressed(KeyCode::P) || (!playtest && keyboard_input.just_pressed(KeyCode::Escape))
+}
+
+fn pause_input(keyboard_input: Res<Input<KeyCode>>, playtest: Option<Res<Playtest>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if pause_key_pressed(&keyboard_input, playtest.is_some()) {
+        playing_state.set(PlayingState::Paused);
+    }
+}
+
+//等待按键时P和Esc交给改键处理
+fn resume_input(keyboard_input: Res<Input<KeyCode>>, playtest: Option<Res<Playtest>>, rebind: Res<FireKeyRebind>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if rebind.0.is_none() && pause_key_pressed(&keyboard_input, playtest.is_some()) {
+        playing_state.set(PlayingState::Running);
+    }
+}
+
+fn pause_button_setup(parent: &mut ChildBuilder, action: PauseAction, text: &str) {
+    parent.spawn(ButtonBundle {
+        style: Style {
+            width: Val::Px(180.0),
+            height: Val::Px(40.0),
+            justify_content: JustifyContent::Center,
+            align_items: AlignItems::Center,
+            margin: UiRect::all(Val::Px(4.)),
+            ..Default::default()
+        },
+        background_color: NORMAL_BUTTON.into(),
+        ..Default::default()
+    }).insert(action.clone()).with_children(|parent| {
+        let mut text = parent.spawn(TextBundle::from_section(
+            text,
+            TextStyle {
+                font_size: 20.0,
+                color: Color::rgb(0.9, 0.9, 0.9),
+                ..Default::default()
+            }));
+        if let PauseAction::FireKey(player1) = action {
+            text.insert(FireKeyLabel(player1));
+        }
+    });
+}
+
+fn pause_text_setup(parent: &mut ChildBuilder, text: String, font_size: f32) {
+    parent.spawn(TextBundle::from_section(text, TextStyle {
+        font_size,
+        color: Color::rgb(0.9, 0.9, 0.9),
+        ..Default::default()
+    }).with_style(Style {
+        margin: UiRect::all(Val::Px(6.)),
+        ..Default::default()
+    }));
+}
+
+fn fire_key_text(player1: bool, fire_keys: &FireKeys, rebind: &FireKeyRebind) -> String {
+    let name = if player1 { "1P" } else { "2P" };
+    if rebind.0 == Some(player1) {
+        format!("{} fire: press a key", name)
+    } else {
+        format!("{} fire: {:?}", name, fire_keys.get(player1))
+    }
+}
+
+//暂停菜单盖在冻结的画面上, 设置页面先隐藏
+fn pause_setup(mut commands: Commands, fire_keys: Res<FireKeys>, rebind: Res<FireKeyRebind>, playtest: Option<Res<Playtest>>) {
+    let mut root = full_screen_node_setup();
+    root.style.position_type = PositionType::Absolute;
+    root.background_color = Color::rgba(0., 0., 0., 0.6).into();
+    root.z_index = ZIndex::Global(10);
+    let mut page = full_screen_node_setup();
+    page.style.height = Val::Auto;
+    commands.spawn(root).insert(PauseOverlay).with_children(|parent| {
+        parent.spawn(page.clone()).insert(PauseMain).with_children(|parent| {
+            pause_text_setup(parent, "PAUSE".to_string(), 40.0);
+            pause_button_setup(parent, PauseAction::Resume, "resume");
+            pause_button_setup(parent, PauseAction::Restart, "restart stage");
+            pause_button_setup(parent, PauseAction::Settings, "settings");
+            pause_button_setup(parent, PauseAction::Quit, "quit to menu");
+        });
+        page.style.display = Display::None;
+        parent.spawn(page).insert(PauseSettings).with_children(|parent| {
+            pause_text_setup(parent, "SETTINGS".to_string(), 40.0);
+            pause_button_setup(parent, PauseAction::FireKey(true), &fire_key_text(true, &fire_keys, &rebind));
+            pause_button_setup(parent, PauseAction::FireKey(false), &fire_key_text(false, &fire_keys, &rebind));
+            pause_text_setup(parent, "1P: W A S D move, 2P: arrow keys move".to_string(), 20.0);
+            if playtest.is_some() {
+                pause_text_setup(parent, "P: pause and resume, Esc: back to editor".to_string(), 20.0);
+            } else {
+                pause_text_setup(parent, "P / Esc: pause and resume".to_string(), 20.0);
+            }
+            pause_button_setup(parent, PauseAction::Back, "back");
+        });
+    });
+}
+
+fn pause_cleanup(mut commands: Commands, mut rebind: ResMut<FireKeyRebind>, query: Query<Entity, With<PauseOverlay>>) {
+    for entity in &query {
+        commands.entity(entity).despawn_recursive();
+    }
+    rebind.0 = None;
+}
+
+//重新开始这一关时玩家保留当前的等级和命数, 退出时回到菜单, 试玩时由菜单转回编辑器
+fn pause_button_system(mut query: Query<(&Interaction, &mut BackgroundColor, &PauseAction), (Changed<Interaction>, With<Button>)>, mut main_page: Query<&mut Style, (With<PauseMain>, Without<PauseSettings>)>, mut settings_page: Query<&mut Style, With<PauseSettings>>, mut rebind: ResMut<FireKeyRebind>, mut game_state: ResMut<NextState<GameState>>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    for (interaction, mut color, action) in &mut query {
+        *color = match *interaction {
+            Interaction::Hovered => HOVERED_BUTTON.into(),
+            Interaction::Pressed => PRESSED_BUTTON.into(),
+            _ => NORMAL_BUTTON.into()
+        };
+        if *interaction != Interaction::Pressed {
+            continue;
+        }
+        match action {
+            PauseAction::Resume => playing_state.set(PlayingState::Running),
+            PauseAction::Restart => playing_state.set(PlayingState::Intro),
+            PauseAction::Settings | PauseAction::Back => {
+                let settings = matches!(action, PauseAction::Settings);
+                for mut style in &mut main_page {
+                    style.display = if settings { Display::None } else { Display::Flex };
+                }
+                for mut style in &mut settings_page {
+                    style.display = if settings { Display::Flex } else { Display::None };
+                }
+                rebind.0 = None;
+            }
+            PauseAction::FireKey(player1) => rebind.0 = Some(*player1),
+            PauseAction::Quit => leave_playing(&mut game_state, &mut playing_state),
+        }
+    }
+}
+
+//点了开炮按键的按钮后按下的键就是新的开炮键, Esc取消
+//P和移动键已经有用处, 也不能和另一个玩家的开炮键一样, 按了不算
+fn fire_key_rebind(keyboard_input: Res<Input<KeyCode>>, mut rebind: ResMut<FireKeyRebind>, mut fire_keys: ResMut<FireKeys>) {
+    let Some(player1) = rebind.0 else {
+        return;
+    };
+    let Some(&key) = keyboard_input.get_just_pressed().next() else {
+        return;
+    };
+    if key == KeyCode::Escape {
+        rebind.0 = None;
+        return;
+    }
+    if key == KeyCode::P || GAME_PLAYER1_KEY_CODE.contains(&key) || GAME_PLAYER2_KEY_CODE.contains(&key) || key == fire_keys.get(!player1) {
+        return;
+    }
+    if player1 {
+        fire_keys.player1 = key;
+    } else {
+        fire_keys.player2 = key;
+    }
+    rebind.0 = None;
+}
+
+fn fire_key_label_update(fire_keys: Res<FireKeys>, rebind: Res<FireKeyRebind>, mut query: Query<(&mut Text, &FireKeyLabel)>) {
+    if !fire_keys.is_changed() && !rebind.is_changed() {
+        return;
+    }
+    for (mut text, label) in &mut query {
+        text.sections[0].value = fire_key_text(label.0, &fire_keys, &rebind);
+    }
+}
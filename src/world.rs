@@ -2,17 +2,25 @@ use std::collections::{HashMap, VecDeque};
 use bevy::prelude::*;
 use lazy_static::lazy_static;
 use serde::Deserialize;
-use crate::GameState;
-use crate::load::{GameConfig, GameTexture};
+use crate::{GameState, PlayingState};
+use crate::bullet::Bullet;
+use crate::enemy::Enemy;
+use crate::level::GameLevel;
+use crate::load::{GameConfig, GameMap, GameTexture, LandType};
+use crate::stage::{PlayEntity, StageEntity};
 use crate::utils::Vec3Ext;
 
 pub struct WorldPlugin;
 
+pub const GAME_MAX_TANK_TIER: u8 = 3;
+
 lazy_static! {
     pub static ref GAME_BLOCK: Vec3 = Vec3::new(24., 24., 0.);
     pub static ref GAME_X_STEP: Vec3 = Vec3::new(24., 0., 0.);
     pub static ref GAME_Y_STEP: Vec3 = Vec3::new(0., 24., 0.);
     pub static ref GAME_Z_INDEX: Vec3 = Vec3::new(0., 0., 1.);
+    pub static ref GAME_TANK_SIZE: Vec2 = Vec2::new(48., 48.);
+    pub static ref GAME_BULLET_SIZE: Vec2 = Vec2::new(12., 12.);
     pub static ref GAME_PLAYER1_KEY_CODE: [KeyCode; 4] = [KeyCode::W, KeyCode::A, KeyCode::S, KeyCode::D];
     pub static ref GAME_PLAYER2_KEY_CODE: [KeyCode; 4] = [KeyCode::Up, KeyCode::Left, KeyCode::Down, KeyCode::Right];
     pub static ref GAME_DIRECTION_SPEED: HashMap<GameDirection, Vec3> = {
@@ -35,9 +43,14 @@ lazy_static! {
 
 impl Plugin for WorldPlugin {
     fn build(&self, app: &mut App) {
-        app.init_resource::<KeyState>().add_systems(Startup, add_camera2d)
-            .add_systems(OnEnter(GameState::Spawn), (spawn_player, spawn_world))
-            .add_systems(Update, (moving_action, player1_turns, player2_turns, player1_moving, player2_moving, save_key_bind).run_if(in_state(GameState::Playing)));
+        app.init_resource::<KeyState>().init_resource::<FireKeys>().init_resource::<RiverFrame>().init_resource::<GameMode>().add_event::<PlayerHit>().add_systems(Startup, add_camera2d)
+            .add_systems(Update, river_update.run_if(resource_exists::<GameTexture>()).run_if(not(in_state(PlayingState::Paused))))
+            .add_systems(OnEnter(PlayingState::Paused), key_state_reset)
+            .add_systems(OnEnter(GameState::Spawn), spawn_player)
+            .add_systems(OnEnter(PlayingState::Intro), (spawn_world, stage_players_setup))
+            .add_systems(OnEnter(GameState::Playing), playing_setup)
+            .add_systems(OnExit(GameState::Playing), key_state_reset)
+            .add_systems(Update, (moving_action, player1_turns, player1_moving, (player2_turns, player2_moving).run_if(resource_equals(GameMode::Double)), save_key_bind, map_tiles_update, player_hit, players_out, shield_update, tier_texture, appear_update).run_if(in_state(PlayingState::Running)));
     }
 }
 
@@ -53,16 +66,137 @@ pub enum GameDirection {
 pub struct Moving {
     pub speed: f32,
     pub direction: GameDirection,
+    //在冰面上还要滑行的距离, 大于0时没有输入也继续前进
+    #[serde(default)]
+    pub slide: f32,
+}
+
+//单人模式只有1P, 双人模式两个玩家分别计命和得分
+#[derive(Clone, Copy, Default, Eq, PartialEq, Debug, Resource)]
+pub enum GameMode {
+    #[default]
+    Single,
+    Double,
 }
 
 #[derive(Component)]
 struct GamePanel;
 
 #[derive(Component)]
-struct Player1;
+pub struct Player1;
+
+#[derive(Component)]
+pub struct Player2;
+
+pub type AnyPlayer = Or<(With<Player1>, With<Player2>)>;
+
+#[derive(Component)]
+pub struct Home;
+
+#[derive(Component)]
+pub struct Lives(pub u32);
+
+//玩家坦克等级, 从1开始
+#[derive(Component)]
+pub struct TankTier(pub u8);
+
+//出场闪光期间坦克隐藏且不能行动, overlay 是挂在坦克下面的闪光贴图
+#[derive(Component)]
+pub struct Appear {
+    timer: Timer,
+    frame: Timer,
+    overlay: Entity,
+}
+
+//护盾期间不会被击中, overlay 是挂在坦克下面的护盾贴图
+#[derive(Component)]
+pub struct Shield {
+    pub timer: Timer,
+    frame: Timer,
+    overlay: Entity,
+}
+
+#[derive(Event)]
+pub struct PlayerHit(pub Entity);
 
+//所有水面共用一个计时器两帧交替, 游戏和编辑器里的水面保持同步
+#[derive(Resource)]
+pub struct RiverFrame {
+    timer: Timer,
+    pub frame: usize,
+}
+
+impl Default for RiverFrame {
+    fn default() -> Self {
+        RiverFrame {
+            timer: Timer::from_seconds(0.5, TimerMode::Repeating),
+            frame: 0,
+        }
+    }
+}
+
+//水面贴图, 精灵和编辑器里的图块都可以挂
 #[derive(Component)]
-struct Player2;
+pub struct River;
+
+//已经生成贴图的地形, 地图变化时只更新有变化的格子, 游戏地图和编辑器画布共用
+pub struct LandTiles {
+    data: Vec<Vec<LandType>>,
+    entities: HashMap<(i32, i32), Vec<Entity>>,
+}
+
+impl LandTiles {
+    //先记成全是空地, 地形贴图在第一次更新时全部生成
+    pub fn new(size: Vec2) -> Self {
+        LandTiles {
+            data: vec![vec![LandType::None; size.x as usize]; size.y as usize],
+            entities: HashMap::new(),
+        }
+    }
+
+    //和已生成的贴图对比, 变化的格子删掉旧贴图再用spawn生成新的
+    pub fn update(&mut self, commands: &mut Commands, data: &[Vec<LandType>], mut spawn: impl FnMut(&mut Commands, (i32, i32), &LandType) -> Vec<Entity>) {
+        for (row, line) in data.iter().enumerate() {
+            for (col, land) in line.iter().enumerate() {
+                if self.data[row][col] == *land {
+                    continue;
+                }
+                self.data[row][col] = land.clone();
+                let cell = (col as i32, row as i32);
+                for entity in self.entities.remove(&cell).unwrap_or_default() {
+                    commands.entity(entity).despawn();
+                }
+                let entities = spawn(commands, cell, land);
+                self.entities.insert(cell, entities);
+            }
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MapTiles(LandTiles);
+
+//两个玩家开炮的按键, 可以在暂停菜单的设置里改
+#[derive(Resource)]
+pub struct FireKeys {
+    pub player1: KeyCode,
+    pub player2: KeyCode,
+}
+
+impl Default for FireKeys {
+    fn default() -> Self {
+        FireKeys {
+            player1: KeyCode::Space,
+            player2: KeyCode::Return,
+        }
+    }
+}
+
+impl FireKeys {
+    pub fn get(&self, player1: bool) -> KeyCode {
+        if player1 { self.player1 } else { self.player2 }
+    }
+}
 
 #[derive(Default, Resource)]
 struct KeyState {
@@ -70,40 +204,234 @@ struct KeyState {
     input2_queue: VecDeque<KeyCode>,
 }
 
+//配置里的坐标以格子为单位
+pub fn to_world_position(position: &Vec3) -> Vec3 {
+    *GAME_X_STEP * position.x + *GAME_Y_STEP * position.y + *GAME_Z_INDEX * position.z
+}
+
+//已经有护盾时只延长时间, 不再挂第二个护盾贴图
+pub fn add_shield(commands: &mut Commands, game_texture: &GameTexture, entity: Entity, shield: Option<Mut<Shield>>, seconds: f32) {
+    if let Some(mut shield) = shield {
+        if shield.timer.remaining_secs() < seconds {
+            shield.timer = Timer::from_seconds(seconds, TimerMode::Once);
+        }
+        return;
+    }
+    let overlay = commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(0),
+        texture_atlas: game_texture.protect.clone(),
+        transform: Transform::from_translation(*GAME_Z_INDEX * 0.5),
+        ..Default::default()
+    }).id();
+    commands.entity(entity).add_child(overlay).insert(Shield {
+        timer: Timer::from_seconds(seconds, TimerMode::Once),
+        frame: Timer::from_seconds(0.1, TimerMode::Repeating),
+        overlay,
+    });
+}
+
+pub fn add_appear(commands: &mut Commands, game_texture: &GameTexture, entity: Entity, seconds: f32) {
+    let overlay = commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(0),
+        texture_atlas: game_texture.appear.clone(),
+        transform: Transform::from_translation(*GAME_Z_INDEX * 0.5),
+        ..Default::default()
+    }).id();
+    commands.entity(entity).add_child(overlay).insert(Appear {
+        timer: Timer::from_seconds(seconds, TimerMode::Once),
+        frame: Timer::from_seconds(0.1, TimerMode::Repeating),
+        overlay,
+    });
+}
+
 fn add_camera2d(mut commands: Commands) {
     commands.spawn(Camera2dBundle::default());
 }
 
-//生成游戏布局
-fn spawn_world(mut commands: Commands, game_texture: Res<GameTexture>) {
+fn playing_setup(mut playing_state: ResMut<NextState<PlayingState>>) {
+    playing_state.set(PlayingState::Intro);
+}
+
+//离开游戏时两个状态在同一帧切换, 只改GameState的话PlayingState要晚一帧才变成Disabled, 中间那一帧游戏系统还会跑
+pub fn leave_playing(game_state: &mut NextState<GameState>, playing_state: &mut NextState<PlayingState>) {
+    game_state.set(GameState::Menu);
+    playing_state.set(PlayingState::Disabled);
+}
+
+//生成一关的布局, 每关开始时重新生成
+fn spawn_world(mut commands: Commands, game_texture: Res<GameTexture>, game_level: Res<GameLevel>) {
+    let game_map = game_level.map.clone();
     commands.spawn(SpriteSheetBundle {
         sprite: TextureAtlasSprite::new(0),
         texture_atlas: game_texture.world.clone(),
         transform: Transform::from_translation(Vec3::ZERO),
         ..Default::default()
-    }).insert(GamePanel);
+    }).insert(GamePanel).insert(StageEntity);
+    commands.spawn(SpriteSheetBundle {
+        sprite: TextureAtlasSprite::new(0),
+        texture_atlas: game_texture.home.clone(),
+        transform: Transform::from_translation(to_world_position(&game_map.home)),
+        ..Default::default()
+    }).insert(Home).insert(StageEntity);
+    commands.insert_resource(MapTiles(LandTiles::new(game_map.size)));
+    commands.insert_resource(game_map);
 }
 
-fn spawn_player(mut commands: Commands, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, mut game_state: ResMut<NextState<GameState>>) {
+//一格地形的贴图, 除了铁块都按四分之一格分别生成, 树盖在坦克上面
+fn spawn_land(commands: &mut Commands, game_texture: &GameTexture, game_map: &GameMap, (col, row): (i32, i32), land: &LandType, step: f32, river_frame: usize) -> Vec<Entity> {
+    let z = if *land == LandType::Tree { 1.8 } else { 0.5 };
+    game_texture.land(land, river_frame).into_iter().map(|(texture_atlas, index, quarter)| {
+        let rect = match quarter {
+            Some(quarter) => game_map.quarter_rect(col, row, quarter, step),
+            None => game_map.cell_rect(col, row, step),
+        };
+        let mut entity = commands.spawn(SpriteSheetBundle {
+            sprite: TextureAtlasSprite {
+                index,
+                color: land.tint(),
+                custom_size: Some(rect.size()),
+                ..Default::default()
+            },
+            texture_atlas,
+            transform: Transform::from_translation(rect.center().extend(z)),
+            ..Default::default()
+        });
+        entity.insert(StageEntity);
+        if *land == LandType::Water {
+            entity.insert(River);
+        }
+        entity.id()
+    }).collect()
+}
+
+fn river_update(time: Res<Time>, game_texture: Res<GameTexture>, mut river_frame: ResMut<RiverFrame>, mut query: Query<&mut Handle<TextureAtlas>, With<River>>) {
+    if !river_frame.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+    river_frame.frame += 1;
+    for mut texture_atlas in &mut query {
+        *texture_atlas = game_texture.river(river_frame.frame);
+    }
+}
+
+//地图数据变化后和已生成的贴图对比, 只重新生成变化的格子
+fn map_tiles_update(mut commands: Commands, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_map: Res<GameMap>, river_frame: Res<RiverFrame>, mut tiles: ResMut<MapTiles>) {
+    if !game_map.is_changed() {
+        return;
+    }
+    let step = game_config.world.step;
+    tiles.0.update(&mut commands, &game_map.data, |commands, cell, land| spawn_land(commands, &game_texture, &game_map, cell, land, step, river_frame.frame));
+}
+
+//出场闪光在每关开始时添加, 2P只在双人模式下生成
+fn spawn_player(mut commands: Commands, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_level: Res<GameLevel>, game_mode: Res<GameMode>, mut game_state: ResMut<NextState<GameState>>) {
     commands.spawn(SpriteSheetBundle {
         sprite: TextureAtlasSprite::new(GAME_TANK_DIRECTION_INDEX[&game_config.player1.moving.direction][0]),
         texture_atlas: game_texture.p1_1.clone(),
-        transform: Transform::from_translation(*GAME_X_STEP * game_config.player1.position.x + *GAME_Y_STEP * game_config.player1.position.y + *GAME_Z_INDEX * game_config.player1.position.z),
+        transform: Transform::from_translation(to_world_position(&game_level.player1)),
         ..Default::default()
-    }).insert(Player1).insert(game_config.player1.moving.clone());
+    }).insert(Player1).insert(game_config.player1.moving.clone()).insert(Lives(game_config.player1.lives)).insert(TankTier(1)).insert(PlayEntity);
     game_state.set(GameState::Playing);
+    if *game_mode != GameMode::Double {
+        return;
+    }
     commands.spawn(SpriteSheetBundle {
         sprite: TextureAtlasSprite::new(GAME_TANK_DIRECTION_INDEX[&game_config.player2.moving.direction][0]),
         texture_atlas: game_texture.p2_1.clone(),
-        transform: Transform::from_translation(*GAME_X_STEP * game_config.player2.position.x + *GAME_Y_STEP * game_config.player2.position.y + *GAME_Z_INDEX * game_config.player2.position.z),
+        transform: Transform::from_translation(to_world_position(&game_level.player2)),
         ..Default::default()
-    }).insert(Player2).insert(game_config.player2.moving.clone());
+    }).insert(Player2).insert(game_config.player2.moving.clone()).insert(Lives(game_config.player2.lives)).insert(TankTier(1)).insert(PlayEntity);
+}
+
+//每关开始时玩家回到这一关的出生点重新出场, 等级和剩余的命带到下一关, 上一关的护盾和闪光去掉
+fn stage_players_setup(mut commands: Commands, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_level: Res<GameLevel>, mut query: Query<(Entity, &mut Transform, &mut Moving, &mut TextureAtlasSprite, Option<&Appear>, Option<&Shield>, Has<Player1>), AnyPlayer>) {
+    for (entity, mut transform, mut moving, mut sprite, appear, shield, is_player1) in &mut query {
+        let (player_config, position) = if is_player1 { (&game_config.player1, &game_level.player1) } else { (&game_config.player2, &game_level.player2) };
+        transform.translation = to_world_position(position);
+        *moving = player_config.moving.clone();
+        sprite.index = GAME_TANK_DIRECTION_INDEX[&moving.direction][0];
+        if let Some(shield) = shield {
+            commands.entity(shield.overlay).despawn_recursive();
+            commands.entity(entity).remove::<Shield>();
+        }
+        if let Some(appear) = appear {
+            commands.entity(appear.overlay).despawn_recursive();
+            commands.entity(entity).remove::<Appear>();
+        }
+        add_appear(&mut commands, &game_texture, entity, game_config.spawn.appear);
+    }
+}
+
+//坦克沿当前方向前进一步后的位置, 按2x2格子的占地检测地形碰撞, 走不动时返回None
+pub fn next_position(translation: Vec3, moving: &Moving, game_config: &GameConfig, game_map: &GameMap) -> Option<Vec3> {
+    let mut next = translation + moving.speed * GAME_DIRECTION_SPEED[&moving.direction];
+    next.boundary(&game_config.world.boundary);
+    if next == translation || game_map.is_blocked(Rect::from_center_size(next.truncate(), *GAME_TANK_SIZE), game_config.world.step) {
+        None
+    } else {
+        Some(next)
+    }
+}
+
+//滑行中每走一步扣掉滑行距离, 滑完或被挡住就停下
+fn moving_action(game_config: Res<GameConfig>, game_map: Res<GameMap>, mut query: Query<(&mut Transform, &mut Moving), (Without<Bullet>, Without<Appear>)>) {
+    for (mut moving_transform, mut moving) in &mut query {
+        let next = next_position(moving_transform.translation, &moving, &game_config, &game_map);
+        if let Some(next) = next {
+            moving_transform.translation = next;
+        }
+        if moving.slide > 0. {
+            moving.slide -= moving.speed;
+            if next.is_none() || moving.slide <= 0. {
+                moving.slide = 0.;
+                moving.speed = 0.;
+            }
+        }
+    }
+}
+
+//松开方向键后停下, 在冰面上沿原方向继续滑行一段距离
+fn stop_moving(moving: &mut Moving, translation: Vec3, game_config: &GameConfig, game_map: &GameMap) {
+    if moving.slide > 0. || moving.speed == 0. {
+        return;
+    }
+    if game_map.is_on_ice(Rect::from_center_size(translation.truncate(), *GAME_TANK_SIZE), game_config.world.step) {
+        moving.slide = game_config.world.ice_slide;
+    } else {
+        moving.speed = 0.;
+    }
 }
 
-fn moving_action(game_config: Res<GameConfig>, mut query: Query<(&mut Transform, &Moving), With<Moving>>) {
-    for (mut moving_transform, moving) in &mut query {
-        moving_transform.translation += moving.speed * GAME_DIRECTION_SPEED[&moving.direction];
-        moving_transform.translation.boundary(&game_config.world.boundary);
+//玩家被击中后少一条命, 降回1级并回到出生点重新出场, 命用完时移除坦克, 有护盾时不受影响
+fn player_hit(mut commands: Commands, mut events: EventReader<PlayerHit>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, game_level: Res<GameLevel>, mut query: Query<(&mut Transform, &mut Moving, &mut Lives, &mut TankTier, Has<Player1>), Without<Shield>>) {
+    for PlayerHit(entity) in events.read() {
+        let Ok((mut transform, mut moving, mut lives, mut tier, is_player1)) = query.get_mut(*entity) else {
+            continue;
+        };
+        let (player_config, position) = if is_player1 { (&game_config.player1, &game_level.player1) } else { (&game_config.player2, &game_level.player2) };
+        lives.0 = lives.0.saturating_sub(1);
+        if lives.0 == 0 {
+            commands.entity(*entity).despawn_recursive();
+            continue;
+        }
+        transform.translation = to_world_position(position);
+        *moving = player_config.moving.clone();
+        tier.0 = 1;
+        add_appear(&mut commands, &game_texture, *entity, game_config.spawn.appear);
+    }
+}
+
+//所有玩家的命都用完时游戏结束
+fn players_out(players: Query<(), AnyPlayer>, mut playing_state: ResMut<NextState<PlayingState>>) {
+    if players.is_empty() {
+        playing_state.set(PlayingState::GameOver);
+    }
+}
+
+//等级变化后换成对应等级的坦克贴图
+fn tier_texture(game_texture: Res<GameTexture>, mut query: Query<(&TankTier, &mut Handle<TextureAtlas>, Has<Player1>), Changed<TankTier>>) {
+    for (tier, mut texture_atlas, is_player1) in &mut query {
+        *texture_atlas = game_texture.player(is_player1, tier.0);
     }
 }
 
@@ -125,7 +453,12 @@ fn player2_turns(mut query: Query<(&mut TextureAtlasSprite, &Moving), With<Playe
     }
 }
 
-fn save_key_bind(mut keys: ResMut<KeyState>, keyboard_input: Res<Input<KeyCode>>) {
+//暂停期间收不到按键松开, 恢复后要重新按下方向键, 退出游戏时也清空, 下一局不会带着上一局按住的键
+fn key_state_reset(mut keys: ResMut<KeyState>) {
+    *keys = KeyState::default();
+}
+
+fn save_key_bind(mut keys: ResMut<KeyState>, keyboard_input: Res<Input<KeyCode>>, game_mode: Res<GameMode>) {
     for key_code in GAME_PLAYER1_KEY_CODE.iter() {
         if keyboard_input.just_pressed(*key_code) {
             keys.input1_queue.push_back(*key_code);
@@ -134,6 +467,9 @@ fn save_key_bind(mut keys: ResMut<KeyState>, keyboard_input: Res<Input<KeyCode>>
         }
     }
 
+    if *game_mode != GameMode::Double {
+        return;
+    }
     for key_code in GAME_PLAYER2_KEY_CODE.iter() {
         if keyboard_input.just_pressed(*key_code) {
             keys.input2_queue.push_back(*key_code);
@@ -143,9 +479,10 @@ fn save_key_bind(mut keys: ResMut<KeyState>, keyboard_input: Res<Input<KeyCode>>
     }
 }
 
-fn player1_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, mut query: Query<&mut Moving, With<Player1>>) {
-    for mut moving in &mut query {
+fn player1_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, game_map: Res<GameMap>, mut query: Query<(&Transform, &mut Moving), With<Player1>>) {
+    for (transform, mut moving) in &mut query {
         if let Some(&key_code) = keys.input1_queue.front() {
+            moving.slide = 0.;
             match key_code {
                 KeyCode::W => {
                     moving.direction = GameDirection::Up;
@@ -166,14 +503,15 @@ fn player1_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, mut quer
                 _ => {}
             }
         } else {
-            moving.speed = 0.
+            stop_moving(&mut moving, transform.translation, &game_config, &game_map);
         }
     }
 }
 
-fn player2_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, mut query: Query<&mut Moving, With<Player2>>) {
-    for mut moving in &mut query {
+fn player2_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, game_map: Res<GameMap>, mut query: Query<(&Transform, &mut Moving), With<Player2>>) {
+    for (transform, mut moving) in &mut query {
         if let Some(&key_code) = keys.input2_queue.front() {
+            moving.slide = 0.;
             match key_code {
                 KeyCode::Up => {
                     moving.direction = GameDirection::Up;
@@ -194,10 +532,45 @@ fn player2_moving(keys: ResMut<KeyState>, game_config: Res<GameConfig>, mut quer
                 _ => {}
             }
         } else {
-            moving.speed = 0.
+            stop_moving(&mut moving, transform.translation, &game_config, &game_map);
         }
     }
 }
 
 
 
+
+//护盾贴图两帧交替, 时间到了移除护盾
+fn shield_update(mut commands: Commands, time: Res<Time>, mut query: Query<(Entity, &mut Shield)>, mut overlays: Query<&mut TextureAtlasSprite>) {
+    for (entity, mut shield) in &mut query {
+        if shield.timer.tick(time.delta()).finished() {
+            commands.entity(entity).remove::<Shield>();
+            commands.entity(shield.overlay).despawn_recursive();
+        } else if shield.frame.tick(time.delta()).just_finished() {
+            if let Ok(mut sprite) = overlays.get_mut(shield.overlay) {
+                sprite.index = 1 - sprite.index;
+            }
+        }
+    }
+}
+
+//闪光贴图循环播放, 结束后坦克现身, 玩家坦克再加上出生护盾
+pub fn appear_update(mut commands: Commands, time: Res<Time>, game_texture: Res<GameTexture>, game_config: Res<GameConfig>, mut query: Query<(Entity, &mut Appear, &mut TextureAtlasSprite, Has<Enemy>, Option<&mut Shield>)>, mut overlays: Query<&mut TextureAtlasSprite, Without<Appear>>) {
+    for (entity, mut appear, mut sprite, is_enemy, shield) in &mut query {
+        if appear.timer.tick(time.delta()).finished() {
+            sprite.color.set_a(1.);
+            commands.entity(entity).remove::<Appear>();
+            commands.entity(appear.overlay).despawn_recursive();
+            if !is_enemy {
+                add_shield(&mut commands, &game_texture, entity, shield, game_config.spawn.shield);
+            }
+            continue;
+        }
+        sprite.color.set_a(0.);
+        if appear.frame.tick(time.delta()).just_finished() {
+            if let Ok(mut overlay) = overlays.get_mut(appear.overlay) {
+                overlay.index = (overlay.index + 1) % 3;
+            }
+        }
+    }
+}